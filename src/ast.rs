@@ -6,6 +6,8 @@ use std::cell::RefCell;
 use std::cmp::Ordering;
 use std::fmt;
 use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::Write;
 use std::ops::Deref;
 use std::rc::Rc;
 
@@ -98,8 +100,41 @@ impl Val {
 
         Ok(out.to_string())
     }
+
+    // Whether the value is of the type, which is one of TYPE_NAMES
+    pub fn has_type(&self, type_name: &str) -> bool {
+        self.type_name().is_ok_and(|actual| type_satisfies(&actual, type_name))
+    }
 }
 
+// Whether a value of the actual type, as given by typeof, can be used where the expected type is
+// annotated. Nil is the empty list, and primitives are functions too.
+pub fn type_satisfies(actual: &str, expected: &str) -> bool {
+    match (actual, expected) {
+        (_, "any") | ("nil", "list") | ("primitive", "function") => true,
+        _ => actual == expected,
+    }
+}
+
+// The types that annotations can name: those given by typeof, and any
+pub const TYPE_NAMES: &[&str] = &[
+    "any",
+    "nil",
+    "error",
+    "number",
+    "string",
+    "char",
+    "symbol",
+    "list",
+    "boolean",
+    "function",
+    "primitive",
+    "writer",
+    "reader",
+    "environment",
+    "cell",
+];
+
 impl PartialOrd for Val {
     fn partial_cmp(&self, other: &Val) -> Option<Ordering> {
         use self::Val::*;
@@ -114,11 +149,67 @@ impl PartialOrd for Val {
 pub struct Node {
     pub val: Val,
     pub loc: Loc,
+    // The metadata attached with ^, as a list of alternating keys and values like
+    // (:private true :tag number)
+    pub meta: Option<Box<Node>>,
 }
 
 impl Node {
     pub fn new(val: Val, loc: Loc) -> Self {
-        Node { val, loc }
+        Node {
+            val,
+            loc,
+            meta: None,
+        }
+    }
+
+    // Adds the keys and values to the metadata, replacing the values of keys it already has
+    pub fn add_meta(mut self, entries: &[Node], loc: Loc) -> Self {
+        let mut merged = match self.meta.take() {
+            Some(meta) => match meta.val {
+                Val::List(children) => children,
+                _ => Vec::new(),
+            },
+            None => Vec::new(),
+        };
+        for entry in entries.chunks(2) {
+            match merged
+                .chunks(2)
+                .position(|existing| existing[0] == entry[0])
+            {
+                Some(i) => merged[i * 2 + 1] = entry[1].clone(),
+                None => merged.extend_from_slice(entry),
+            }
+        }
+        self.meta = Some(Box::new(Node::new(Val::List(merged), loc)));
+        self
+    }
+
+    // The value of a key in the metadata, as in meta_get(":private")
+    pub fn meta_get(&self, key: &str) -> Option<&Node> {
+        match self.meta {
+            Some(ref meta) => match meta.val {
+                Val::List(ref entries) => entries
+                    .chunks(2)
+                    .find(|entry| match entry[0].val {
+                        Val::Symbol(ref s) => s == key,
+                        _ => false,
+                    }).and_then(|entry| entry.get(1)),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    // The type the node is annotated with, as in ^number x, if any
+    pub fn type_annotation(&self) -> Option<&str> {
+        match self.meta_get(":tag") {
+            Some(&Node {
+                val: Val::Symbol(ref type_name),
+                ..
+            }) => Some(type_name),
+            _ => None,
+        }
     }
 
     pub fn as_print_friendly_string(&self) -> String {
@@ -249,6 +340,10 @@ pub struct RoutineObj {
     pub body: Box<Node>,
     pub lexical_env: SmartEnv,
     pub routine_type: RoutineType,
+    // The string given after the params, if any
+    pub doc: Option<String>,
+    // The type of value it returns, if its params are annotated with one
+    pub return_type: Option<String>,
 }
 
 pub type PrimitiveFnPointer = fn(SmartEnv, Node, Vec<Node>) -> NodeResult;
@@ -259,13 +354,98 @@ pub struct PrimitiveObj {
     pub f: PrimitiveFnPointer,
     pub min_arity: isize,
     pub max_arity: isize,
+    pub doc: Option<PrimitiveDoc>,
+}
+
+// Documentation of a primitive, which has no params of its own to show
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct PrimitiveDoc {
+    // Written like the params of a routine, as in "(x &rest xs)"
+    pub arglist: &'static str,
+    pub doc: &'static str,
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum WriterObj {
     Sink,
     Standard,
+    StandardError,
     Buffer(Rc<RefCell<Vec<u8>>>),
+    Stream(StreamObj),
+}
+
+impl WriterObj {
+    // Writes are passed straight through to the underlying stream, which may buffer them.
+    // Call flush to guarantee that everything written so far has reached its destination.
+    pub fn write_str(&self, s: &str) -> io::Result<()> {
+        match self {
+            WriterObj::Sink => Ok(()),
+            WriterObj::Standard => io::stdout().write_all(s.as_bytes()),
+            WriterObj::StandardError => io::stderr().write_all(s.as_bytes()),
+            WriterObj::Buffer(b) => b.borrow_mut().write_all(s.as_bytes()),
+            WriterObj::Stream(obj) => obj.with_stream(|stream| stream.write_all(s.as_bytes())),
+        }
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        match self {
+            WriterObj::Sink => Ok(()),
+            WriterObj::Standard => io::stdout().flush(),
+            WriterObj::StandardError => io::stderr().flush(),
+            WriterObj::Buffer(..) => Ok(()),
+            WriterObj::Stream(obj) => obj.with_stream(|stream| stream.flush()),
+        }
+    }
+
+    // Flushes the writer and, when it is a stream, closes it, after which writing to it fails.
+    // Other writers stay open.
+    pub fn close(&self) -> io::Result<()> {
+        self.flush()?;
+        if let WriterObj::Stream(obj) = self {
+            obj.stream.borrow_mut().take();
+        }
+        Ok(())
+    }
+}
+
+// Wraps any host io::Write (a file, a pipe, a socket, ...) so that it can be used as a writer.
+// The stream is dropped once the writer is closed.
+#[derive(Clone)]
+pub struct StreamObj {
+    pub name: String,
+    pub stream: Rc<RefCell<Option<Box<dyn Write>>>>,
+}
+
+impl StreamObj {
+    pub fn new<W: Write + 'static>(name: &str, stream: W) -> Self {
+        StreamObj {
+            name: name.to_string(),
+            stream: Rc::new(RefCell::new(Some(Box::new(stream)))),
+        }
+    }
+
+    fn with_stream<F>(&self, f: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut Box<dyn Write>) -> io::Result<()>,
+    {
+        match *self.stream.borrow_mut() {
+            Some(ref mut stream) => f(stream),
+            None => Err(io::Error::other(format!("{} is closed", self.name))),
+        }
+    }
+}
+
+// Two streams are only equal if they share the same underlying host stream
+impl PartialEq for StreamObj {
+    fn eq(&self, other: &StreamObj) -> bool {
+        Rc::ptr_eq(&self.stream, &other.stream)
+    }
+}
+
+impl fmt::Debug for StreamObj {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "StreamObj {{ name: {:?} }}", self.name)
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -0,0 +1,477 @@
+use ast::{Node, Val};
+use back::env::SmartEnv;
+use back::runtime_error::RuntimeError;
+use coverage::Coverage;
+use debugger::Debugger;
+use loc::Loc;
+use profile::Profiler;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeSet;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Limits on how much work an evaluation may do. A limit of None means unlimited.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Limits {
+    // Number of thunks the trampoline may execute, across all nested trampolines
+    pub max_steps: Option<u64>,
+    // How deeply trampolines may nest, which bounds the host stack used by non-tail calls
+    pub max_depth: Option<usize>,
+    // A budget for the list elements and string bytes of every value produced, counting those
+    // nested in lists. Values are copied as they are passed around, so this bounds the total an
+    // evaluation allocates, not the memory it holds at any one time: a long loop uses up the
+    // budget even if it keeps little data.
+    pub max_allocations: Option<u64>,
+}
+
+// A test defined by deftest, whose routine takes no arguments and runs the body of the test
+#[derive(Debug, Clone)]
+pub struct TestDef {
+    pub name: String,
+    pub routine: Node,
+    pub loc: Loc,
+}
+
+// State shared by every environment descended from the same root environment, so that the
+// trampoline can reach it from whichever environment it is currently evaluating in.
+#[derive(Debug)]
+pub struct EvalContext {
+    pub limits: Limits,
+    steps: Cell<u64>,
+    allocations: Cell<u64>,
+    // The location of each nested evaluation in progress, outermost first
+    frames: RefCell<Vec<Loc>>,
+    // The frames in progress when the most recent error was raised, innermost first
+    error_stack: RefCell<Option<Vec<Loc>>>,
+    interrupt: Arc<AtomicBool>,
+    // Registered by deftest and use-fixture, for test runners to find
+    tests: RefCell<Vec<TestDef>>,
+    fixtures: RefCell<Vec<Node>>,
+    // The state of the generator behind rand-int and shuffle
+    random_state: Cell<u64>,
+    // Hits on lines of code, while coverage is enabled
+    coverage: RefCell<Option<Coverage>>,
+    // Times calls, while profiling is enabled
+    profiler: RefCell<Option<Profiler>>,
+    // The names of the routines and primitives whose calls are traced, and how many traced
+    // calls are in progress
+    traced: RefCell<BTreeSet<String>>,
+    trace_depth: Cell<usize>,
+    // Decides where to stop, while debugging is enabled
+    debugger: RefCell<Option<Debugger>>,
+}
+
+// Contexts have no meaningful notion of equality beyond identity, but environments need one
+impl PartialEq for EvalContext {
+    fn eq(&self, other: &EvalContext) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+// Unlimited, and with the random generator seeded from the clock like any other context
+impl Default for EvalContext {
+    fn default() -> Self {
+        EvalContext::new(Limits::default())
+    }
+}
+
+impl EvalContext {
+    pub fn new(limits: Limits) -> Self {
+        EvalContext {
+            limits,
+            steps: Cell::new(0),
+            allocations: Cell::new(0),
+            frames: RefCell::new(Vec::new()),
+            error_stack: RefCell::new(None),
+            interrupt: Arc::new(AtomicBool::new(false)),
+            tests: RefCell::new(Vec::new()),
+            fixtures: RefCell::new(Vec::new()),
+            random_state: Cell::new(time_seed()),
+            coverage: RefCell::new(None),
+            profiler: RefCell::new(None),
+            traced: RefCell::new(BTreeSet::new()),
+            trace_depth: Cell::new(0),
+            debugger: RefCell::new(None),
+        }
+    }
+
+    // Setting the returned flag, from any thread, interrupts the evaluation currently running
+    // in this context with a RuntimeError::Interrupted
+    pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.interrupt)
+    }
+
+    // The flag is cleared once the interrupt has been delivered, so that the environment can
+    // be used for further evaluations
+    pub fn check_interrupt(&self, loc: &Loc) -> Result<(), RuntimeError> {
+        if self.interrupt.swap(false, Ordering::SeqCst) {
+            Err(RuntimeError::Interrupted(loc.clone()))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn add_test(&self, test: TestDef) {
+        self.tests.borrow_mut().push(test);
+    }
+
+    pub fn tests(&self) -> Vec<TestDef> {
+        self.tests.borrow().clone()
+    }
+
+    // A fixture is a function that is given each test as a function taking no arguments, and
+    // which must call it
+    pub fn add_fixture(&self, fixture: Node) {
+        self.fixtures.borrow_mut().push(fixture);
+    }
+
+    pub fn fixtures(&self) -> Vec<Node> {
+        self.fixtures.borrow().clone()
+    }
+
+    // Makes the numbers generated from here on the same as after any other seeding with the
+    // same seed
+    pub fn seed_random(&self, seed: u64) {
+        self.random_state.set(seed);
+    }
+
+    // The next number from a SplitMix64 generator, which is fast and good enough for testing,
+    // though not for cryptography
+    pub fn next_random(&self) -> u64 {
+        let state = self.random_state.get().wrapping_add(0x9E37_79B9_7F4A_7C15);
+        self.random_state.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // A number from 0 up to, but not including, the bound, which must not be 0
+    pub fn next_random_below(&self, bound: u64) -> u64 {
+        // Numbers past the largest multiple of the bound are rejected, to avoid favoring
+        // small numbers
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let n = self.next_random();
+            if n < zone {
+                return n % bound;
+            }
+        }
+    }
+
+    pub fn trace(&self, name: &str) {
+        self.traced.borrow_mut().insert(name.to_string());
+    }
+
+    pub fn untrace(&self, name: &str) {
+        self.traced.borrow_mut().remove(name);
+    }
+
+    pub fn untrace_all(&self) {
+        self.traced.borrow_mut().clear();
+    }
+
+    // Sorted by name
+    pub fn traced(&self) -> Vec<String> {
+        self.traced.borrow().iter().cloned().collect()
+    }
+
+    pub fn is_traced(&self, name: &str) -> bool {
+        self.traced.borrow().contains(name)
+    }
+
+    // Returns how many traced calls the call being entered is within
+    pub fn trace_enter(&self) -> usize {
+        let depth = self.trace_depth.get();
+        self.trace_depth.set(depth + 1);
+        depth
+    }
+
+    // Returns how many traced calls the call being left is within
+    pub fn trace_leave(&self) -> usize {
+        let depth = self.trace_depth.get().saturating_sub(1);
+        self.trace_depth.set(depth);
+        depth
+    }
+
+    pub fn enable_coverage(&self) {
+        let mut coverage = self.coverage.borrow_mut();
+        if coverage.is_none() {
+            *coverage = Some(Coverage::new());
+        }
+    }
+
+    pub fn take_coverage(&self) -> Option<Coverage> {
+        self.coverage.borrow_mut().take()
+    }
+
+    pub fn record_coverage(&self, loc: &Loc) {
+        if let Some(ref mut coverage) = *self.coverage.borrow_mut() {
+            coverage.record(loc);
+        }
+    }
+
+    pub fn enable_profiling(&self) {
+        let mut profiler = self.profiler.borrow_mut();
+        if profiler.is_none() {
+            *profiler = Some(Profiler::new());
+        }
+    }
+
+    pub fn take_profile(&self) -> Option<Profiler> {
+        self.profiler.borrow_mut().take()
+    }
+
+    // The name is only made while profiling, as making it may be costly
+    pub fn profile_function<F: FnOnce() -> String>(&self, name: F) {
+        if let Some(ref mut profiler) = *self.profiler.borrow_mut() {
+            profiler.enter_function(name(), self.frames.borrow().len());
+        }
+    }
+
+    // Must be followed by a call of profile_leave, once the call being profiled returns
+    pub fn profile_enter<F: FnOnce() -> String>(&self, name: F) {
+        if let Some(ref mut profiler) = *self.profiler.borrow_mut() {
+            profiler.enter(name(), self.frames.borrow().len());
+        }
+    }
+
+    pub fn profile_leave(&self) {
+        if let Some(ref mut profiler) = *self.profiler.borrow_mut() {
+            profiler.leave();
+        }
+    }
+
+    // Enables debugging if it isn't already. The function must not evaluate anything.
+    pub fn with_debugger<R, F: FnOnce(&mut Debugger) -> R>(&self, f: F) -> R {
+        let mut debugger = self.debugger.borrow_mut();
+        f(debugger.get_or_insert_with(Debugger::new))
+    }
+
+    // Why the evaluation should stop before evaluating the node in the environment, if it should
+    pub fn debug_stop_reason(&self, node: &Node, env: &SmartEnv) -> Option<String> {
+        match *self.debugger.borrow_mut() {
+            Some(ref mut debugger) => debugger.should_stop(node, env, self.depth()),
+            None => None,
+        }
+    }
+
+    pub fn debug_enter_routine(&self, name: Option<&str>) {
+        if let Some(ref mut debugger) = *self.debugger.borrow_mut() {
+            debugger.enter_routine(name, self.depth());
+        }
+    }
+
+    // How deeply the trampoline currently running is nested
+    pub fn depth(&self) -> usize {
+        self.frames.borrow().len()
+    }
+
+    // The location of each nested evaluation in progress, outermost first
+    pub fn frames(&self) -> Vec<Loc> {
+        self.frames.borrow().clone()
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps.get()
+    }
+
+    pub fn enter(&self, loc: &Loc) -> Result<(), RuntimeError> {
+        let depth = self.frames.borrow().len() + 1;
+        if let Some(max) = self.limits.max_depth {
+            if depth > max {
+                return Err(limit_exceeded("nesting depth", max as u64, loc));
+            }
+        }
+        self.frames.borrow_mut().push(loc.clone());
+        Ok(())
+    }
+
+    pub fn exit(&self) {
+        if let Some(ref mut profiler) = *self.profiler.borrow_mut() {
+            profiler.leave_depth(self.frames.borrow().len());
+        }
+        if let Some(ref mut debugger) = *self.debugger.borrow_mut() {
+            debugger.leave_depth(self.frames.borrow().len());
+        }
+        self.frames.borrow_mut().pop();
+    }
+
+    // Called as an error leaves each nested evaluation. Only the first call for an error
+    // records anything, because only then are all of the frames it was raised in still present.
+    pub fn record_error_stack(&self) {
+        let mut error_stack = self.error_stack.borrow_mut();
+        if error_stack.is_none() {
+            let frames = self.frames.borrow();
+            let stack = frames
+                .iter()
+                .rev()
+                .filter(|loc| **loc != Loc::Unknown)
+                .cloned()
+                .collect();
+            *error_stack = Some(stack);
+        }
+    }
+
+    // The stack of the most recent error, which is then forgotten
+    pub fn take_error_stack(&self) -> Vec<Loc> {
+        self.error_stack.borrow_mut().take().unwrap_or_default()
+    }
+
+    pub fn step(&self, loc: &Loc) -> Result<(), RuntimeError> {
+        self.check_interrupt(loc)?;
+
+        let steps = self.steps.get() + 1;
+        if let Some(max) = self.limits.max_steps {
+            if steps > max {
+                return Err(limit_exceeded("evaluation steps", max, loc));
+            }
+        }
+        self.steps.set(steps);
+        Ok(())
+    }
+
+    // Charges the value to the allocation budget, along with everything nested in it
+    pub fn allocate(&self, node: &Node) -> Result<(), RuntimeError> {
+        if let Some(max) = self.limits.max_allocations {
+            let allocations = self.allocations.get() + size(node, max - self.allocations.get());
+            if allocations > max {
+                return Err(limit_exceeded("total allocations", max, &node.loc));
+            }
+            self.allocations.set(allocations);
+        }
+        Ok(())
+    }
+
+    // Each top-level evaluation gets fresh step and allocation budgets, and interrupts requested while
+    // nothing was running are discarded. Nested evaluations, such as those started by load,
+    // share the state of the evaluation that started them. Every evaluation forgets the stack
+    // of any earlier error.
+    pub fn begin_evaluation(&self) {
+        self.error_stack.borrow_mut().take();
+        if self.frames.borrow().is_empty() {
+            self.steps.set(0);
+            self.allocations.set(0);
+            self.interrupt.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+// The number of list elements and string bytes in the value, at any depth. Counting stops once
+// the size is known to be over the limit.
+fn size(node: &Node, limit: u64) -> u64 {
+    let mut size = 0;
+    let mut pending = vec![node];
+    while let Some(node) = pending.pop() {
+        match node.val {
+            Val::StringVal(ref s) => size += s.len() as u64,
+            Val::List(ref children) => {
+                size += children.len() as u64;
+                pending.extend(children.iter());
+            }
+            _ => (),
+        }
+        if size > limit {
+            break;
+        }
+    }
+    size
+}
+
+fn limit_exceeded(limit_name: &str, limit: u64, loc: &Loc) -> RuntimeError {
+    RuntimeError::LimitExceeded {
+        limit_name: limit_name.to_string(),
+        limit,
+        loc: loc.clone(),
+    }
+}
+
+// Mixes in how many seeds were taken before, so that contexts created within the resolution of
+// the clock still generate different numbers
+fn time_seed() -> u64 {
+    static SEEDS_TAKEN: AtomicU64 = AtomicU64::new(0);
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() ^ (u64::from(d.subsec_nanos()) << 32))
+        .unwrap_or(0);
+    let taken = SEEDS_TAKEN.fetch_add(1, Ordering::Relaxed);
+    time ^ taken.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use back;
+    use front;
+    use std::rc::Rc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_interrupt_from_another_thread() {
+        let env = ::test_env();
+        let flag = back::interrupt_handle(&env);
+
+        let interrupter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            flag.store(true, Ordering::SeqCst);
+        });
+        let nodes = front::parse("host", "(def loop (fn () (loop)))\n(loop)").unwrap();
+        let result = back::eval(Rc::clone(&env), nodes);
+        interrupter.join().unwrap();
+
+        assert_eq!(
+            result.map_err(|e| e.display()),
+            Err("Evaluation interrupted".to_string())
+        );
+        assert_eq!(::parse_eval_print(env, "host", "(+ 1 2)"), "3");
+    }
+
+    #[test]
+    fn test_eval_with_timeout_interrupts_for_loop() {
+        let env = ::test_env();
+
+        let nodes = front::parse("host", "(for i 0 2000000000 i)").unwrap();
+        let result = back::eval_with_timeout(Rc::clone(&env), nodes, Duration::from_millis(50));
+
+        assert_eq!(
+            result.map_err(|e| e.display()),
+            Err("Evaluation interrupted".to_string())
+        );
+        assert_eq!(::parse_eval_print(env, "host", "(+ 1 2)"), "3");
+    }
+
+    #[test]
+    fn test_random_numbers_repeat_after_seeding() {
+        let context = EvalContext::new(Limits::default());
+        context.seed_random(42);
+        let first: Vec<u64> = (0..100).map(|_| context.next_random_below(6)).collect();
+        context.seed_random(42);
+        let second: Vec<u64> = (0..100).map(|_| context.next_random_below(6)).collect();
+
+        assert_eq!(first, second);
+        assert!(first.iter().all(|&n| n < 6));
+        assert!((0..6).all(|n| first.contains(&n)));
+    }
+
+    #[test]
+    fn test_fresh_root_envs_generate_different_numbers() {
+        let program = "(list (rand-int 1000000000) (rand-int 1000000000))";
+        let first = ::parse_eval_print(::test_env(), "host", program);
+        let second = ::parse_eval_print(::test_env(), "host", program);
+
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_eval_with_timeout_returns_result_in_time() {
+        let env = ::test_env();
+
+        let nodes = front::parse("host", "(+ 1 2)").unwrap();
+        let result = back::eval_with_timeout(env, nodes, Duration::from_secs(10));
+
+        assert_eq!(result.map(|n| n.val), Ok(Val::Number(3)));
+    }
+}
@@ -1,4 +1,5 @@
 use ast::{Node, Val};
+use back::context::{EvalContext, Limits};
 use back::runtime_error::RuntimeError;
 use loc::Loc;
 use std::cell::RefCell;
@@ -12,6 +13,7 @@ pub struct Env {
     pub name: String,
     pub map: HashMap<String, Node>,
     pub parent: Option<SmartEnv>,
+    pub context: Rc<EvalContext>,
 }
 
 impl Env {
@@ -20,10 +22,16 @@ impl Env {
             None => "TopLevel",
             Some(..) => "Local",
         };
+        // Child environments share the evaluation context of their root environment
+        let context = match parent {
+            None => Rc::new(EvalContext::new(Limits::default())),
+            Some(ref parent_env) => Rc::clone(&parent_env.borrow().context),
+        };
         let e = Env {
             name: name.to_string(),
             map: HashMap::new(),
             parent,
+            context,
         };
         Rc::new(RefCell::new(e))
     }
@@ -63,6 +71,17 @@ impl Env {
         }
     }
 
+    // All names visible from this environment, including those of its ancestors
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.map.keys().cloned().collect();
+        if let Some(ref parent_env) = self.parent {
+            names.append(&mut parent_env.borrow().names());
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
     pub fn remove(&mut self, k: &str) -> Option<Node> {
         let val = self.map.remove(k);
         // Reinsert nil here so that a later update will update the correct hashmap
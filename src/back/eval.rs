@@ -4,12 +4,48 @@ use back::runtime_error::{check_args, RuntimeError};
 use back::specials;
 use back::trampoline;
 use back::trampoline::{ContinuationResult, Flag};
+use debugger;
 use loc::Loc;
 use std::rc::Rc;
 
 pub type NodeResult = Result<Node, RuntimeError>;
 
+// The names handled directly by eval_list rather than looked up in the environment
+pub const SPECIAL_FORMS: &[&str] = &[
+    "def",
+    "quote",
+    "list",
+    "fn",
+    "macro",
+    "macroexpand1",
+    "if",
+    "cond",
+    "for",
+    "let",
+    "update!",
+    "begin",
+    "with-output-to-string",
+    "deftest",
+];
+
+// The args fn and macro take: params and a body, with a documentation string between them. A
+// third arg that isn't a string is taken to be a second form of the body, which isn't allowed.
+pub fn routine_arity(args: &[Node]) -> (isize, isize) {
+    match args.get(1) {
+        Some(&Node {
+            val: Val::StringVal(..),
+            ..
+        }) => (2, 3),
+        _ => (2, 2),
+    }
+}
+
 pub fn eval_node(env: SmartEnv, node: Node, _: Vec<Node>, _: Flag) -> ContinuationResult {
+    env.borrow().context.record_coverage(&node.loc);
+    let stop_reason = env.borrow().context.debug_stop_reason(&node, &env);
+    if let Some(reason) = stop_reason {
+        debugger::prompt(&env, &node.loc, &reason)?;
+    }
     match node.val {
         Val::List(..) => Ok(trampoline::bounce(eval_list, env, node)),
         Val::Symbol(name) => match env.borrow_mut().get(&name) {
@@ -65,6 +101,11 @@ pub fn eval_list(env: SmartEnv, node: Node, _: Vec<Node>, flag: Flag) -> Continu
 
     let head_node = args.remove(0);
     let head_value = head_node.val;
+    // Errors in evaluating the head, such as an undefined name, point at the head itself
+    let head_loc = match head_node.loc {
+        Loc::Unknown => loc.clone(),
+        head_loc => head_loc,
+    };
 
     match head_value {
         Val::Symbol(ref name) => match name.as_ref() {
@@ -81,11 +122,13 @@ pub fn eval_list(env: SmartEnv, node: Node, _: Vec<Node>, flag: Flag) -> Continu
                 return specials::eval_special_list(env, loc, args);
             }
             "fn" => {
-                check_args("fn", &loc, &args, 2, 2)?;
+                let (min, max) = routine_arity(&args);
+                check_args("fn", &loc, &args, min, max)?;
                 return specials::eval_special_routine(env, args, RoutineType::Function);
             }
             "macro" => {
-                check_args("macro", &loc, &args, 2, 2)?;
+                let (min, max) = routine_arity(&args);
+                check_args("macro", &loc, &args, min, max)?;
                 return specials::eval_special_routine(env, args, RoutineType::Macro);
             }
             "macroexpand1" => {
@@ -116,16 +159,21 @@ pub fn eval_list(env: SmartEnv, node: Node, _: Vec<Node>, flag: Flag) -> Continu
                 check_args("begin", &loc, &args, 0, -1)?;
                 return specials::eval_special_begin(env, args);
             }
+            "with-output-to-string" => {
+                check_args("with-output-to-string", &loc, &args, 0, -1)?;
+                return specials::eval_special_with_output_to_string(env, loc, args);
+            }
+            "deftest" => {
+                check_args("deftest", &loc, &args, 1, -1)?;
+                return specials::eval_special_deftest(env, loc, args);
+            }
             _ => {}
         },
         _ => {}
     }
 
-    let mut evaled_head = trampoline::run(
-        eval_node,
-        Rc::clone(&env),
-        Node::new(head_value, loc.clone()),
-    )?;
+    let mut evaled_head =
+        trampoline::run(eval_node, Rc::clone(&env), Node::new(head_value, head_loc))?;
 
     // Sometimes the evaled head will lack a location. When that happens, the location needs
     // to be set to the location of the unevaled head, to allow for good error messages.
@@ -170,8 +218,18 @@ pub fn eval_invoke_procedure(
         )),
         Val::Primitive(ref obj) => {
             check_args(&obj.name, &head.loc, &args, obj.min_arity, obj.max_arity)?;
-            let out = (obj.f)(Rc::clone(&env), head.clone(), args)?;
-            Ok(trampoline::finish(out))
+            let context = Rc::clone(&env.borrow().context);
+            let traced = context.is_traced(&obj.name);
+            if traced {
+                trace_call(&env, &obj.name, &args, &head.loc)?;
+            }
+            context.profile_enter(|| obj.name.clone());
+            let out = (obj.f)(Rc::clone(&env), head.clone(), args);
+            context.profile_leave();
+            if traced {
+                trace_return(&env, &obj.name, &out, &head.loc)?;
+            }
+            Ok(trampoline::finish(out?))
         }
         _ => Err(RuntimeError::CannotInvokeNonProcedure(
             head.val.to_string(),
@@ -214,6 +272,13 @@ pub fn eval_invoke_routine(
             });
         }
 
+        // The args are used up by binding them, so a traced call keeps a copy to write
+        let context = Rc::clone(&dynamic_env.borrow().context);
+        let traced_call = match robj.name {
+            Some(ref name) if context.is_traced(name) => Some((name.clone(), args.clone())),
+            _ => None,
+        };
+
         // Create the lexical environment based on the procedure's lexical parent
         let lexical_env = Env::new(Some(parent_lexical_env));
 
@@ -232,6 +297,9 @@ pub fn eval_invoke_routine(
                     }
 
                     let rest_param = params.remove(0);
+                    for arg in &args {
+                        check_arg_type(&robj.name, &rest_param, arg, &loc)?;
+                    }
                     match rest_param.val {
                         Val::Symbol(name) => {
                             let l = Node::new(Val::List(args), rest_param.loc);
@@ -241,23 +309,63 @@ pub fn eval_invoke_routine(
                         v => return Err(RuntimeError::ParamsMustBeSymbols(v, loc)),
                     }
                 }
-                Val::Symbol(name) => {
+                Val::Symbol(ref name) => {
                     let arg = if args.len() > 0 {
                         args.remove(0)
                     } else {
                         return Err(RuntimeError::Unknown("not enough args".to_string(), loc));
                     };
 
-                    lexical_env.borrow_mut().define(&name, arg)?;
+                    check_arg_type(&robj.name, &param, &arg, &loc)?;
+                    lexical_env.borrow_mut().define(name, arg)?;
                 }
                 v => return Err(RuntimeError::ParamsMustBeSymbols(v, loc)),
             }
         }
 
+        // A call is only traced once its args are bound, as one that fails to bind them never
+        // returns to be traced
+        let traced_name = match traced_call {
+            Some((name, args)) => {
+                trace_call(&dynamic_env, &name, &args, &loc)?;
+                Some(name)
+            }
+            None => None,
+        };
+
+        if robj.routine_type == RoutineType::Function {
+            context.debug_enter_routine(robj.name.as_deref());
+        }
+
+        let return_type = robj.return_type;
+        let routine_name = robj.name.clone();
+
+        // Anonymous routines are named by where they are defined
+        let name = robj.name;
+        let routine_loc = loc.clone();
+        let profile_name = move || match name {
+            Some(name) => name,
+            None => match routine_loc {
+                Loc::File {
+                    filename,
+                    line,
+                    column,
+                    ..
+                } => format!("(fn {}:{}:{})", filename, line, column),
+                Loc::Unknown => "(fn)".to_string(),
+            },
+        };
+
         // Evaluate the application of the routine
         match robj.routine_type {
             RoutineType::Macro => {
-                let expanded_macro = trampoline::run(eval_node, lexical_env, *body)?;
+                context.profile_enter(profile_name);
+                let expanded_macro = trampoline::run(eval_node, lexical_env, *body);
+                context.profile_leave();
+                if let Some(ref name) = traced_name {
+                    trace_return(&dynamic_env, name, &expanded_macro, &loc)?;
+                }
+                let expanded_macro = expanded_macro?;
 
                 match flag {
                     Flag::None => {
@@ -271,7 +379,20 @@ pub fn eval_invoke_routine(
                 }
             }
             RoutineType::Function => {
-                return Ok(trampoline::bounce(eval_node, lexical_env, *body));
+                context.profile_function(profile_name);
+                if traced_name.is_none() && return_type.is_none() {
+                    return Ok(trampoline::bounce(eval_node, lexical_env, *body));
+                }
+
+                // The value of the body must be checked or traced, which is left to the
+                // trampoline, so that the body can still make a tail call
+                let ret = RoutineReturn {
+                    routine_name,
+                    return_type,
+                    trace_env: traced_name.map(|_| dynamic_env),
+                    loc,
+                };
+                return Ok(trampoline::bounce_then(eval_node, lexical_env, *body, ret));
             }
         }
     }
@@ -281,3 +402,132 @@ pub fn eval_invoke_routine(
         loc,
     ));
 }
+
+// What is left to do with the value of a routine's body once it has been evaluated: checking it
+// against the routine's return type, if it has one, and then tracing it, if the call is traced
+pub struct RoutineReturn {
+    routine_name: Option<String>,
+    return_type: Option<String>,
+    // Where the traced call was made from
+    trace_env: Option<SmartEnv>,
+    loc: Loc,
+}
+
+impl RoutineReturn {
+    pub fn apply(self, output: NodeResult) -> NodeResult {
+        let routine_name = self.routine_name;
+        let loc = self.loc;
+        let output = match self.return_type {
+            Some(expected) => output.and_then(|output| {
+                if output.val.has_type(&expected) {
+                    Ok(output)
+                } else {
+                    Err(RuntimeError::UnexpectedReturnType {
+                        procedure_name: routine_name.clone().unwrap_or_else(|| "fn".to_string()),
+                        expected_type_name: expected,
+                        actual_val: output.val,
+                        loc: loc.clone(),
+                    })
+                }
+            }),
+            None => output,
+        };
+        if let (Some(env), Some(name)) = (self.trace_env, routine_name) {
+            trace_return(&env, &name, &output, &loc)?;
+        }
+        output
+    }
+
+    // Whether the other return is redundant when this one is applied first. An untraced check
+    // of the same type passes or fails along with this one.
+    pub fn supersedes(&self, other: &RoutineReturn) -> bool {
+        self.trace_env.is_none() && other.trace_env.is_none()
+            && self.return_type == other.return_type
+    }
+}
+
+// Fails unless the arg has the type that the param is annotated with, if any
+fn check_arg_type(
+    routine_name: &Option<String>,
+    param: &Node,
+    arg: &Node,
+    loc: &Loc,
+) -> Result<(), RuntimeError> {
+    match param.type_annotation() {
+        Some(expected) if !arg.val.has_type(expected) => {
+            Err(RuntimeError::UnexpectedArgumentType {
+                procedure_name: routine_name.clone().unwrap_or_else(|| "fn".to_string()),
+                expected_type_name: expected.to_string(),
+                actual_val: arg.val.clone(),
+                // The arg's own location, if it has one, is where the value came from
+                loc: match arg.loc {
+                    Loc::Unknown => loc.clone(),
+                    ref arg_loc => arg_loc.clone(),
+                },
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+// Writes the call to *writer*, indented by how many traced calls it is within
+fn trace_call(env: &SmartEnv, name: &str, args: &[Node], loc: &Loc) -> Result<(), RuntimeError> {
+    let depth = env.borrow().context.trace_enter();
+    let mut call = vec![name.to_string()];
+    call.extend(args.iter().map(|arg| format!("{}", arg.val)));
+    write_trace(env, depth, &format!("({})", call.join(" ")), loc)
+}
+
+// Writes what the call returned, or the error it failed with, level with the call
+fn trace_return(
+    env: &SmartEnv,
+    name: &str,
+    output: &NodeResult,
+    loc: &Loc,
+) -> Result<(), RuntimeError> {
+    let depth = env.borrow().context.trace_leave();
+    let line = match *output {
+        Ok(ref node) => format!("{} returned {}", name, node.val),
+        Err(ref e) => format!("{} failed: {}", name, e.display()),
+    };
+    write_trace(env, depth, &line, loc)
+}
+
+fn write_trace(env: &SmartEnv, depth: usize, line: &str, loc: &Loc) -> Result<(), RuntimeError> {
+    let writer = match env.borrow().get("*writer*") {
+        Some(Node {
+            val: Val::Writer(w),
+            ..
+        }) => w,
+        _ => {
+            return Err(RuntimeError::UndefinedName(
+                "*writer*".to_string(),
+                loc.clone(),
+            ))
+        }
+    };
+    writer
+        .write_str(&format!("{}{}\n", "  ".repeat(depth), line))
+        .map_err(|e| RuntimeError::Unknown(format!("Problem while writing: {}", e), loc.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    #[test]
+    fn test_trace_after_call_fails_to_bind_args() {
+        let (env, buffer) = ::test_env_with_buffer(::test_reader());
+        ::parse_eval_print(
+            Rc::clone(&env),
+            "t.mn",
+            "(def f (fn (^number x) x))\n(trace 'f)\n(f \"one\")",
+        );
+        ::parse_eval_print(env, "t.mn", "(f 2)");
+
+        assert_eq!(
+            String::from_utf8_lossy(&buffer.borrow()),
+            "(f 2)\nf returned 2\n"
+        );
+    }
+}
@@ -1,36 +1,198 @@
+pub mod context;
 pub mod env;
 pub mod eval;
 mod primitives;
 pub mod runtime_error;
+pub mod sandbox;
 mod specials;
 mod trampoline;
 
-use ast::{Node, ReaderObj, Val, WriterObj};
+use ast::{Node, ReaderObj, RoutineType, Val, WriterObj};
+use back::context::EvalContext;
 use back::env::{Env, SmartEnv};
 use back::eval::NodeResult;
 use back::runtime_error::RuntimeError;
+use back::sandbox::SandboxConfig;
+use back::trampoline::Flag;
+use coverage::Coverage;
+use debugger::Breakpoint;
 use loc::Loc;
+use profile::Profiler;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 pub fn create_root_env(writer: WriterObj, reader: ReaderObj) -> Result<SmartEnv, RuntimeError> {
     let env = Env::new(None);
+    define_host_bindings(&env, writer, WriterObj::StandardError, reader)?;
+    primitives::init_env_with_primitives(&env)?;
+    Ok(env)
+}
 
-    env.borrow_mut()
-        .define("*writer*", Node::new(Val::Writer(writer), Loc::Unknown))?;
-
-    env.borrow_mut()
-        .define("*reader*", Node::new(Val::Reader(reader), Loc::Unknown))?;
+// Creates a root environment for running untrusted code. Only the primitives allowed by the
+// config are installed, all error output goes to the given writer, and evaluations in this
+// environment are bound by the config's limits.
+pub fn create_sandboxed_root_env(
+    writer: WriterObj,
+    reader: ReaderObj,
+    config: SandboxConfig,
+) -> Result<SmartEnv, RuntimeError> {
+    let env = Env::new(None);
+    env.borrow_mut().context = Rc::new(EvalContext::new(config.limits.clone()));
 
+    define_host_bindings(&env, writer.clone(), writer, reader)?;
     primitives::init_env_with_primitives(&env)?;
+
+    env.borrow_mut().map.retain(|name, node| match node.val {
+        Val::Primitive(..) => config.allows_primitive(name),
+        _ => true,
+    });
+
     Ok(env)
 }
 
+fn define_host_bindings(
+    env: &SmartEnv,
+    writer: WriterObj,
+    error_writer: WriterObj,
+    reader: ReaderObj,
+) -> Result<(), RuntimeError> {
+    let mut menv = env.borrow_mut();
+
+    menv.define("*writer*", Node::new(Val::Writer(writer), Loc::Unknown))?;
+    menv.define(
+        "*error-writer*",
+        Node::new(Val::Writer(error_writer), Loc::Unknown),
+    )?;
+    menv.define("*reader*", Node::new(Val::Reader(reader), Loc::Unknown))?;
+
+    // Hosts that run scripts replace this with their own arguments
+    menv.define(
+        "*command-line-args*",
+        Node::new(Val::List(Vec::new()), Loc::Unknown),
+    )
+}
+
 pub fn eval(env: SmartEnv, values: Vec<Node>) -> NodeResult {
     let mut output = Node::new(Val::Error("NO-INPUT".to_string()), Loc::Unknown); // TODO: should this be nil?
 
+    let context = Rc::clone(&env.borrow().context);
+    context.begin_evaluation();
+
     for val in values {
         output = trampoline::run(eval::eval_node, Rc::clone(&env), val)?;
     }
 
     Ok(output)
 }
+
+// Repeatedly expands the node for as long as it is a call to a macro, without evaluating the
+// final expansion
+pub fn macroexpand(env: SmartEnv, node: Node) -> NodeResult {
+    let mut current = node;
+    while is_macro_call(&env, &current) {
+        current = trampoline::run_with_flag(
+            eval::eval_list,
+            Rc::clone(&env),
+            current,
+            Flag::DelayMacroEvaluation,
+        )?;
+    }
+    Ok(current)
+}
+
+fn is_macro_call(env: &SmartEnv, node: &Node) -> bool {
+    let name = match node.val {
+        Val::List(ref children) if !children.is_empty() => match children[0].val {
+            Val::Symbol(ref name) => name.clone(),
+            _ => return false,
+        },
+        _ => return false,
+    };
+
+    if eval::SPECIAL_FORMS.contains(&name.as_ref()) {
+        return false;
+    }
+
+    match env.borrow().get(&name) {
+        Some(Node {
+            val: Val::Routine(ref robj),
+            ..
+        }) => robj.routine_type == RoutineType::Macro,
+        _ => false,
+    }
+}
+
+// Returns the locations of the evaluations that were in progress when the most recent error in
+// the env's root environment was raised, innermost first, and forgets them
+pub fn take_error_stack(env: &SmartEnv) -> Vec<Loc> {
+    env.borrow().context.take_error_stack()
+}
+
+// Starts counting, in the env's root environment, how often each line of code is evaluated
+pub fn enable_coverage(env: &SmartEnv) {
+    env.borrow().context.enable_coverage();
+}
+
+// Returns the coverage counted since coverage was enabled, and stops counting
+pub fn take_coverage(env: &SmartEnv) -> Option<Coverage> {
+    env.borrow().context.take_coverage()
+}
+
+// Starts timing, in the env's root environment, each call of a routine or primitive
+pub fn enable_profiling(env: &SmartEnv) {
+    env.borrow().context.enable_profiling();
+}
+
+// Returns the profile made since profiling was enabled, and stops profiling
+pub fn take_profile(env: &SmartEnv) -> Option<Profiler> {
+    env.borrow().context.take_profile()
+}
+
+// Starts debugging in the env's root environment, stopping at the breakpoints
+pub fn enable_debugger(env: &SmartEnv, breakpoints: Vec<Breakpoint>) {
+    env.borrow().context.with_debugger(|debugger| {
+        for breakpoint in breakpoints {
+            debugger.add_breakpoint(breakpoint);
+        }
+    });
+}
+
+// Stops at the next form evaluated in the env's root environment, debugging if not already
+pub fn debug_step(env: &SmartEnv) {
+    env.borrow()
+        .context
+        .with_debugger(|debugger| debugger.step());
+}
+
+// Returns a flag that interrupts whatever evaluation is running in the env's root environment
+// when set. The flag is Send, so it can be handed to another thread or a signal handler.
+pub fn interrupt_handle(env: &SmartEnv) -> Arc<AtomicBool> {
+    env.borrow().context.interrupt_flag()
+}
+
+// Evaluates like eval, but interrupts the evaluation if it is still running after the timeout
+pub fn eval_with_timeout(env: SmartEnv, values: Vec<Node>, timeout: Duration) -> NodeResult {
+    let flag = interrupt_handle(&env);
+    let watchdog_flag = Arc::clone(&flag);
+    let (done_sender, done_receiver) = mpsc::channel::<()>();
+
+    let watchdog = thread::spawn(move || {
+        if let Err(mpsc::RecvTimeoutError::Timeout) = done_receiver.recv_timeout(timeout) {
+            watchdog_flag.store(true, Ordering::SeqCst);
+        }
+    });
+
+    let result = eval(env, values);
+
+    let _ = done_sender.send(());
+    let _ = watchdog.join();
+
+    // The watchdog may have fired just after the evaluation finished
+    flag.store(false, Ordering::SeqCst);
+
+    result
+}
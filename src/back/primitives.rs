@@ -1,16 +1,26 @@
 /* Primitives are build-in functions */
 
-use ast::{CellObj, Node, PrimitiveFnPointer, PrimitiveObj, ReaderObj, Val, WriterObj};
+use ast::{
+    CellObj, Node, PrimitiveDoc, PrimitiveFnPointer, PrimitiveObj, ReaderObj, StreamObj, Val,
+    WriterObj,
+};
 use back::env::{Env, SmartEnv};
 use back::eval;
 use back::eval::NodeResult;
 use back::runtime_error::RuntimeError;
 use back::trampoline;
+use debugger;
 use front;
 use loc::Loc;
 use std::cell::RefMut;
-use std::fs::File;
+use std::env;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
 
 pub fn init_env_with_primitives(env: &SmartEnv) -> Result<(), RuntimeError> {
     let mut menv = env.borrow_mut();
@@ -29,6 +39,12 @@ pub fn init_env_with_primitives(env: &SmartEnv) -> Result<(), RuntimeError> {
     def_prim(&mut menv, "read-line", prim_read_line, 0, 0)?;
     def_prim(&mut menv, "print", prim_print, 0, -1)?;
     def_prim(&mut menv, "println", prim_println, 0, -1)?;
+    def_prim(&mut menv, "eprint", prim_eprint, 0, -1)?;
+    def_prim(&mut menv, "eprintln", prim_eprintln, 0, -1)?;
+    def_prim(&mut menv, "flush", prim_flush, 0, 1)?;
+    def_prim(&mut menv, "open-file-writer", prim_open_file_writer, 1, 2)?;
+    def_prim(&mut menv, "open-process-writer", prim_open_process_writer, 1, -1)?;
+    def_prim(&mut menv, "close-writer", prim_close_writer, 1, 1)?;
     def_prim(&mut menv, "not", prim_not, 1, 1)?;
     def_prim(&mut menv, "apply", prim_apply, 2, 2)?;
     def_prim(&mut menv, "typeof", prim_typeof, 1, 1)?;
@@ -42,6 +58,21 @@ pub fn init_env_with_primitives(env: &SmartEnv) -> Result<(), RuntimeError> {
     def_prim(&mut menv, "len", prim_len, 1, 1)?;
     def_prim(&mut menv, "trim-string", prim_trim_string, 1, 1)?;
 
+    def_prim(&mut menv, "read-file", prim_read_file, 1, 1)?;
+    def_prim(&mut menv, "write-file", prim_write_file, 2, 2)?;
+    def_prim(&mut menv, "append-file", prim_append_file, 2, 2)?;
+    def_prim(&mut menv, "file-exists?", prim_file_exists, 1, 1)?;
+    def_prim(&mut menv, "list-dir", prim_list_dir, 1, 1)?;
+    def_prim(&mut menv, "mkdir", prim_mkdir, 1, 1)?;
+    def_prim(&mut menv, "delete-file", prim_delete_file, 1, 1)?;
+    def_prim(&mut menv, "path-join", prim_path_join, 1, -1)?;
+    def_prim(&mut menv, "path-split", prim_path_split, 1, 1)?;
+
+    def_prim(&mut menv, "getenv", prim_getenv, 1, 1)?;
+    def_prim(&mut menv, "setenv", prim_setenv, 2, 2)?;
+    def_prim(&mut menv, "exit", prim_exit, 0, 1)?;
+    def_prim(&mut menv, "run-process", prim_run_process, 1, -1)?;
+
     def_prim(
         &mut menv,
         "current-environment",
@@ -52,6 +83,22 @@ pub fn init_env_with_primitives(env: &SmartEnv) -> Result<(), RuntimeError> {
     def_prim(&mut menv, "eval", prim_eval, 1, 2)?;
     def_prim(&mut menv, "read-string", prim_read_string, 1, 1)?;
     def_prim(&mut menv, "readable-string", prim_readable_string, 1, 1)?;
+    def_prim(&mut menv, "doc", prim_doc, 1, 1)?;
+    def_prim(&mut menv, "arglists", prim_arglists, 1, 1)?;
+    def_prim(&mut menv, "meta", prim_meta, 1, 1)?;
+    def_prim(&mut menv, "with-meta", prim_with_meta, 2, 2)?;
+
+    def_prim(&mut menv, "is", prim_is, 1, 2)?;
+    def_prim(&mut menv, "assert=", prim_assert_equal, 2, 2)?;
+    def_prim(&mut menv, "use-fixture", prim_use_fixture, 1, 1)?;
+
+    def_prim(&mut menv, "rand-int", prim_rand_int, 1, 2)?;
+    def_prim(&mut menv, "rand-seed!", prim_rand_seed, 1, 1)?;
+    def_prim(&mut menv, "shuffle", prim_shuffle, 1, 1)?;
+
+    def_prim(&mut menv, "break", prim_break, 0, 0)?;
+    def_prim(&mut menv, "trace", prim_trace, 0, -1)?;
+    def_prim(&mut menv, "untrace", prim_untrace, 0, -1)?;
 
     def_prim(&mut menv, "cell", prim_cell, 1, 1)?;
     def_prim(&mut menv, "set-cell!", prim_set_cell, 2, 2)?;
@@ -63,6 +110,228 @@ pub fn init_env_with_primitives(env: &SmartEnv) -> Result<(), RuntimeError> {
     Ok(())
 }
 
+// The arglist and documentation of each primitive, as given by arglists and doc. The types of
+// the params, and of the value returned, are annotated where they are fixed.
+const PRIMITIVE_DOCS: &[(&str, &str, &str)] = &[
+    ("+", "^number (^number a ^number b)", "The sum of two numbers"),
+    ("-", "^number (^number a ^number b)", "The difference of two numbers"),
+    ("=", "^boolean (a b)", "Whether two values are equal"),
+    ("<", "^boolean (^number a ^number b)", "Whether the first number is less than the second"),
+    (">", "^boolean (^number a ^number b)", "Whether the first number is greater than the second"),
+    (
+        "panic",
+        "(&rest xs)",
+        "Fails with the values printed as the message",
+    ),
+    (
+        "read-line",
+        "^string ()",
+        "The next line from *reader*, with its newline",
+    ),
+    ("print", "(&rest xs)", "Writes the values to *writer*"),
+    (
+        "println",
+        "(&rest xs)",
+        "Writes the values to *writer*, followed by a newline",
+    ),
+    ("eprint", "(&rest xs)", "Writes the values to *error-writer*"),
+    (
+        "eprintln",
+        "(&rest xs)",
+        "Writes the values to *error-writer*, followed by a newline",
+    ),
+    (
+        "flush",
+        "(&rest writer)",
+        "Makes sure that everything written to the writer, or to *writer*, has reached its destination",
+    ),
+    (
+        "open-file-writer",
+        "^writer (^string path &rest ^boolean append)",
+        "A writer to the file, which replaces its contents, or adds to them if append is true",
+    ),
+    (
+        "open-process-writer",
+        "^writer (^string program &rest ^string args)",
+        "Runs the program with a writer to its standard input, which close-writer waits for",
+    ),
+    (
+        "close-writer",
+        "(^writer writer)",
+        "Flushes the writer and closes it, if it is a file or process",
+    ),
+    ("not", "^boolean (x)", "Whether the value is false or nil"),
+    ("apply", "(f ^list args)", "Calls the function with the list of arguments"),
+    ("typeof", "^symbol (x)", "The name of the value's type, as a symbol"),
+    (
+        "load",
+        "(^string path)",
+        "Evaluates the file in the root environment",
+    ),
+    (
+        "str",
+        "^string (&rest xs)",
+        "A string of the values printed one after another",
+    ),
+    (
+        "concat",
+        "(&rest colls)",
+        "The lists, or the strings, joined together",
+    ),
+    (
+        "cons",
+        "(x coll)",
+        "The list or string with the element added at the front",
+    ),
+    (
+        "first",
+        "(coll)",
+        "The first element of a list or string, or nil if it is empty",
+    ),
+    (
+        "rest",
+        "(coll)",
+        "A list or string of all but the first element",
+    ),
+    ("len", "^number (coll)", "The number of elements of a list or string"),
+    (
+        "trim-string",
+        "^string (^string s)",
+        "The string without whitespace at its start and end",
+    ),
+    ("read-file", "^string (^string path)", "The contents of the file, as a string"),
+    (
+        "write-file",
+        "(^string path ^string contents)",
+        "Replaces the contents of the file with the string",
+    ),
+    (
+        "append-file",
+        "(^string path ^string contents)",
+        "Adds the string to the end of the file",
+    ),
+    ("file-exists?", "^boolean (^string path)", "Whether there is a file or directory at the path"),
+    ("list-dir", "^list (^string path)", "The names of the entries of the directory"),
+    ("mkdir", "(^string path)", "Creates the directory, along with any parents it lacks"),
+    ("delete-file", "(^string path)", "Deletes the file"),
+    (
+        "path-join",
+        "^string (^string path &rest ^string paths)",
+        "The paths joined with the separator of the host",
+    ),
+    ("path-split", "^list (^string path)", "The components of the path"),
+    (
+        "getenv",
+        "(^string name)",
+        "The value of the environment variable, or nil if it isn't set",
+    ),
+    ("setenv", "(^string name ^string value)", "Sets the environment variable"),
+    (
+        "exit",
+        "(&rest ^number code)",
+        "Ends the process with the code, or 0, after flushing the writers",
+    ),
+    (
+        "run-process",
+        "^list (^string program &rest ^string args)",
+        "Runs the program, returning a list of its exit status, standard output and standard error",
+    ),
+    (
+        "current-environment",
+        "^environment ()",
+        "The environment this is called in",
+    ),
+    (
+        "eval",
+        "(expr &rest env)",
+        "Evaluates the expression in the environment, or in the one this is called in",
+    ),
+    (
+        "read-string",
+        "(^string s)",
+        "The first value written in the string, unevaluated",
+    ),
+    (
+        "readable-string",
+        "^string (x)",
+        "The value written as it would be read",
+    ),
+    (
+        "doc",
+        "(x)",
+        "The documentation of a routine or primitive, or of the one a symbol names",
+    ),
+    (
+        "arglists",
+        "(x)",
+        "A list of the params of a routine or primitive, or of the one a symbol names",
+    ),
+    (
+        "meta",
+        "(x)",
+        "The metadata of the value, as a list of keys and values, or nil if it has none",
+    ),
+    (
+        "with-meta",
+        "(x meta)",
+        "The value with the list of keys and values as its metadata, in place of any it had",
+    ),
+    (
+        "is",
+        "(x &rest message)",
+        "Fails the test, with the message if given, unless the value is true",
+    ),
+    ("assert=", "(expected actual)", "Fails the test unless the values are equal"),
+    (
+        "use-fixture",
+        "(f)",
+        "Wraps each test in the function, which is given the test and must call it",
+    ),
+    (
+        "rand-int",
+        "^number (^number n &rest ^number high)",
+        "A random number from 0 up to n, or from n up to high, excluding n or high",
+    ),
+    (
+        "rand-seed!",
+        "(^number seed)",
+        "Makes the random numbers from here on repeatable",
+    ),
+    (
+        "shuffle",
+        "(coll)",
+        "The list or string with its elements in a random order",
+    ),
+    (
+        "break",
+        "()",
+        "Stops at a debugger prompt in the environment this is called in",
+    ),
+    (
+        "trace",
+        "(&rest names)",
+        "Writes each call of the named routines or primitives to *writer*",
+    ),
+    (
+        "untrace",
+        "(&rest names)",
+        "Stops tracing the names, or every name if none are given",
+    ),
+    ("cell", "^cell (x)", "A cell holding the value"),
+    ("set-cell!", "(^cell cell x)", "Makes the cell hold the value"),
+    ("get-cell", "(^cell cell)", "The value the cell holds"),
+    (
+        "_host_inspect_",
+        "(x)",
+        "Prints the host's representation of the value",
+    ),
+    (
+        "_host_backtrace_",
+        "()",
+        "Prints the host's stack",
+    ),
+];
+
 fn def_prim(
     mut_env: &mut RefMut<Env>,
     name: &'static str,
@@ -78,6 +347,10 @@ fn def_prim(
                 f: f,
                 min_arity,
                 max_arity,
+                doc: PRIMITIVE_DOCS
+                    .iter()
+                    .find(|&&(n, _, _)| n == name)
+                    .map(|&(_, arglist, doc)| PrimitiveDoc { arglist, doc }),
             }),
             Loc::Unknown,
         ),
@@ -181,17 +454,26 @@ fn prim_read_line(env: SmartEnv, _head: Node, _args: Vec<Node>) -> NodeResult {
 }
 
 fn prim_print(env: SmartEnv, head: Node, args: Vec<Node>) -> NodeResult {
-    prim_print_or_println(env, head, args, false)
+    prim_print_or_println(env, head, args, "*writer*", false)
 }
 
 fn prim_println(env: SmartEnv, head: Node, args: Vec<Node>) -> NodeResult {
-    prim_print_or_println(env, head, args, true)
+    prim_print_or_println(env, head, args, "*writer*", true)
+}
+
+fn prim_eprint(env: SmartEnv, head: Node, args: Vec<Node>) -> NodeResult {
+    prim_print_or_println(env, head, args, "*error-writer*", false)
+}
+
+fn prim_eprintln(env: SmartEnv, head: Node, args: Vec<Node>) -> NodeResult {
+    prim_print_or_println(env, head, args, "*error-writer*", true)
 }
 
 fn prim_print_or_println(
     env: SmartEnv,
     _head: Node,
     args: Vec<Node>,
+    writer_name: &str,
     add_newline: bool,
 ) -> NodeResult {
     let mut v = Vec::new();
@@ -207,24 +489,153 @@ fn prim_print_or_println(
         format!("{}", &v.join(" "))
     };
 
-    match env.borrow().get("*writer*") {
-        Some(node) => match node.val {
-            Val::Writer(WriterObj::Standard) => {
-                print!("{}", output);
-            }
-            Val::Writer(WriterObj::Buffer(b)) => {
-                use std::io::Write;
-                let mut rm_buffer = b.borrow_mut();
-                write!(&mut rm_buffer, "{}", output).expect("unable to write to buffer");
-            }
-            v => return Err(RuntimeError::UnexpectedValue("writer".to_string(), v, loc)),
-        },
-        _ => panic!("expected writer value"),
+    let writer = lookup_writer(&env, writer_name, &loc)?;
+    if let Err(e) = writer.write_str(&output) {
+        return Err(RuntimeError::Unknown(
+            format!("Problem while writing: {}", e),
+            loc,
+        ));
     }
 
     Ok(Node::new(Val::Nil, loc))
 }
 
+fn prim_flush(env: SmartEnv, head: Node, mut args: Vec<Node>) -> NodeResult {
+    let writer = if !args.is_empty() {
+        let n = args.remove(0);
+        match n.val {
+            Val::Writer(w) => w,
+            v => {
+                return Err(RuntimeError::UnexpectedValue(
+                    "writer".to_string(),
+                    v,
+                    n.loc,
+                ))
+            }
+        }
+    } else {
+        lookup_writer(&env, "*writer*", &head.loc)?
+    };
+
+    match writer.flush() {
+        Ok(()) => Ok(Node::new(Val::Nil, head.loc)),
+        Err(e) => Err(RuntimeError::Unknown(
+            format!("Problem while flushing: {}", e),
+            head.loc,
+        )),
+    }
+}
+
+fn prim_open_file_writer(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let path_node = args.remove(0);
+    let path = expect_string_arg("open-file-writer", &path_node)?;
+    let append = !args.is_empty() && args.remove(0).as_host_boolean()?;
+
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(&path);
+
+    match file {
+        Ok(file) => {
+            let stream = StreamObj::new(&format!("file '{}'", path), io::BufWriter::new(file));
+            Ok(Node::new(Val::Writer(WriterObj::Stream(stream)), path_node.loc))
+        }
+        Err(e) => Err(io_failure("open file", &path, e, &path_node.loc)),
+    }
+}
+
+fn prim_open_process_writer(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let program_node = args.remove(0);
+    let program = expect_string_arg("open-process-writer", &program_node)?;
+    let loc = program_node.loc;
+
+    let mut process_args = Vec::new();
+    for arg in args {
+        process_args.push(expect_string_arg("open-process-writer", &arg)?);
+    }
+
+    let child = process::Command::new(&program)
+        .args(&process_args)
+        .stdin(process::Stdio::piped())
+        .spawn();
+
+    match child {
+        Ok(child) => {
+            let stream = StreamObj::new(&format!("process '{}'", program), ProcessInput { child });
+            Ok(Node::new(Val::Writer(WriterObj::Stream(stream)), loc))
+        }
+        Err(e) => Err(io_failure("run process", &program, e, &loc)),
+    }
+}
+
+// The standard input of a running process. Dropping it ends the input and waits for the process
+// to exit, so that it has finished with what was written once the writer is closed.
+struct ProcessInput {
+    child: process::Child,
+}
+
+impl Write for ProcessInput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.child.stdin {
+            Some(ref mut stdin) => stdin.write(buf),
+            None => Err(io::Error::other("no standard input")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.child.stdin {
+            Some(ref mut stdin) => stdin.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ProcessInput {
+    fn drop(&mut self) {
+        self.child.stdin.take();
+        let _ = self.child.wait();
+    }
+}
+
+fn prim_close_writer(_env: SmartEnv, head: Node, mut args: Vec<Node>) -> NodeResult {
+    let n = args.remove(0);
+    let writer = match n.val {
+        Val::Writer(w) => w,
+        v => {
+            return Err(RuntimeError::UnexpectedValue(
+                "writer".to_string(),
+                v,
+                n.loc,
+            ))
+        }
+    };
+
+    match writer.close() {
+        Ok(()) => Ok(Node::new(Val::Nil, head.loc)),
+        Err(e) => Err(RuntimeError::Unknown(
+            format!("Problem while closing: {}", e),
+            head.loc,
+        )),
+    }
+}
+
+fn lookup_writer(env: &SmartEnv, name: &str, loc: &Loc) -> Result<WriterObj, RuntimeError> {
+    match env.borrow().get(name) {
+        Some(node) => match node.val {
+            Val::Writer(w) => Ok(w),
+            v => Err(RuntimeError::UnexpectedValue(
+                "writer".to_string(),
+                v,
+                loc.clone(),
+            )),
+        },
+        None => Err(RuntimeError::UndefinedName(name.to_string(), loc.clone())),
+    }
+}
+
 fn prim_apply(env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
     let f = args.remove(0);
     let f_args_node = args.remove(0);
@@ -266,11 +677,10 @@ fn prim_load(env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
         }
     };
 
-    let mut f = File::open(filename.to_string()).expect("file not found");
-
-    let mut contents = String::new();
-    f.read_to_string(&mut contents)
-        .expect("something went wrong reading the file");
+    let contents = match fs::read_to_string(&filename) {
+        Ok(contents) => contents,
+        Err(e) => return Err(io_failure("load file", &filename, e, &filename_node.loc)),
+    };
 
     let output = ::parse_eval_print(env, &filename, &contents);
     if output != "nil".to_string() {
@@ -378,6 +788,220 @@ fn prim_trim_string(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeRes
     }
 }
 
+fn prim_read_file(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let path_node = args.remove(0);
+    let path = expect_string_arg("read-file", &path_node)?;
+
+    match fs::read_to_string(&path) {
+        Ok(contents) => Ok(Node::new(Val::StringVal(contents), path_node.loc)),
+        Err(e) => Err(io_failure("read file", &path, e, &path_node.loc)),
+    }
+}
+
+fn prim_write_file(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let path_node = args.remove(0);
+    let path = expect_string_arg("write-file", &path_node)?;
+    let contents = expect_string_arg("write-file", &args.remove(0))?;
+
+    match fs::write(&path, contents) {
+        Ok(()) => Ok(Node::new(Val::Nil, path_node.loc)),
+        Err(e) => Err(io_failure("write file", &path, e, &path_node.loc)),
+    }
+}
+
+fn prim_append_file(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let path_node = args.remove(0);
+    let path = expect_string_arg("append-file", &path_node)?;
+    let contents = expect_string_arg("append-file", &args.remove(0))?;
+
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut f| f.write_all(contents.as_bytes()));
+
+    match result {
+        Ok(()) => Ok(Node::new(Val::Nil, path_node.loc)),
+        Err(e) => Err(io_failure("append to file", &path, e, &path_node.loc)),
+    }
+}
+
+fn prim_file_exists(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let path_node = args.remove(0);
+    let path = expect_string_arg("file-exists?", &path_node)?;
+
+    let output = Path::new(&path).exists();
+
+    Ok(Node::new(Val::Boolean(output), path_node.loc))
+}
+
+fn prim_list_dir(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let path_node = args.remove(0);
+    let path = expect_string_arg("list-dir", &path_node)?;
+
+    let entries = match fs::read_dir(&path) {
+        Ok(entries) => entries,
+        Err(e) => return Err(io_failure("list directory", &path, e, &path_node.loc)),
+    };
+
+    let mut names = Vec::new();
+    for entry in entries {
+        match entry {
+            Ok(entry) => names.push(entry.file_name().to_string_lossy().to_string()),
+            Err(e) => return Err(io_failure("list directory", &path, e, &path_node.loc)),
+        }
+    }
+
+    // The order of read_dir is platform-dependent, so sort to keep scripts deterministic
+    names.sort();
+
+    let children = names
+        .into_iter()
+        .map(|name| Node::new(Val::StringVal(name), path_node.loc.clone()))
+        .collect();
+
+    Ok(Node::new(Val::List(children), path_node.loc))
+}
+
+fn prim_mkdir(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let path_node = args.remove(0);
+    let path = expect_string_arg("mkdir", &path_node)?;
+
+    match fs::create_dir_all(&path) {
+        Ok(()) => Ok(Node::new(Val::Nil, path_node.loc)),
+        Err(e) => Err(io_failure("create directory", &path, e, &path_node.loc)),
+    }
+}
+
+fn prim_delete_file(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let path_node = args.remove(0);
+    let path = expect_string_arg("delete-file", &path_node)?;
+
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(Node::new(Val::Nil, path_node.loc)),
+        Err(e) => Err(io_failure("delete file", &path, e, &path_node.loc)),
+    }
+}
+
+fn prim_path_join(_env: SmartEnv, _head: Node, args: Vec<Node>) -> NodeResult {
+    let mut path = PathBuf::new();
+    let mut loc = Loc::Unknown;
+    for arg in args {
+        path.push(expect_string_arg("path-join", &arg)?);
+        loc = arg.loc;
+    }
+
+    let output = path.to_string_lossy().to_string();
+
+    Ok(Node::new(Val::StringVal(output), loc))
+}
+
+fn prim_path_split(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let path_node = args.remove(0);
+    let path = expect_string_arg("path-split", &path_node)?;
+
+    let children = Path::new(&path)
+        .components()
+        .map(|c| {
+            let part = c.as_os_str().to_string_lossy().to_string();
+            Node::new(Val::StringVal(part), path_node.loc.clone())
+        })
+        .collect();
+
+    Ok(Node::new(Val::List(children), path_node.loc))
+}
+
+fn prim_getenv(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let name_node = args.remove(0);
+    let name = expect_string_arg("getenv", &name_node)?;
+
+    match env::var(&name) {
+        Ok(value) => Ok(Node::new(Val::StringVal(value), name_node.loc)),
+        Err(_) => Ok(Node::new(Val::Nil, name_node.loc)),
+    }
+}
+
+fn prim_setenv(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let name_node = args.remove(0);
+    let name = expect_string_arg("setenv", &name_node)?;
+    let value = expect_string_arg("setenv", &args.remove(0))?;
+
+    env::set_var(&name, &value);
+
+    Ok(Node::new(Val::Nil, name_node.loc))
+}
+
+// Terminates the host process, so anything written so far is flushed first
+fn prim_exit(env: SmartEnv, head: Node, mut args: Vec<Node>) -> NodeResult {
+    let code = if !args.is_empty() {
+        args.remove(0).as_host_number()?
+    } else {
+        0
+    };
+
+    for writer_name in &["*writer*", "*error-writer*"] {
+        if let Ok(writer) = lookup_writer(&env, writer_name, &head.loc) {
+            let _ = writer.flush();
+        }
+    }
+
+    process::exit(code)
+}
+
+// Returns a list of the exit status (nil if the process was killed by a signal), the captured
+// standard output, and the captured standard error
+fn prim_run_process(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let program_node = args.remove(0);
+    let program = expect_string_arg("run-process", &program_node)?;
+    let loc = program_node.loc;
+
+    let mut process_args = Vec::new();
+    for arg in args {
+        process_args.push(expect_string_arg("run-process", &arg)?);
+    }
+
+    let output = match process::Command::new(&program).args(&process_args).output() {
+        Ok(output) => output,
+        Err(e) => return Err(io_failure("run process", &program, e, &loc)),
+    };
+
+    let status = match output.status.code() {
+        Some(code) => Val::Number(code),
+        None => Val::Nil,
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+
+    let children = vec![
+        Node::new(status, loc.clone()),
+        Node::new(Val::StringVal(stdout), loc.clone()),
+        Node::new(Val::StringVal(stderr), loc.clone()),
+    ];
+
+    Ok(Node::new(Val::List(children), loc))
+}
+
+fn expect_string_arg(procedure_name: &str, node: &Node) -> Result<String, RuntimeError> {
+    match node.val {
+        Val::StringVal(ref s) => Ok(s.clone()),
+        ref v => Err(RuntimeError::UnexpectedArgumentType {
+            procedure_name: procedure_name.to_string(),
+            expected_type_name: "string".to_string(),
+            actual_val: v.clone(),
+            loc: node.loc.clone(),
+        }),
+    }
+}
+
+fn io_failure(operation: &str, path: &str, error: io::Error, loc: &Loc) -> RuntimeError {
+    RuntimeError::IoFailure {
+        operation: operation.to_string(),
+        path: path.to_string(),
+        message: error.to_string(),
+        loc: loc.clone(),
+    }
+}
+
 fn prim_current_environment(env: SmartEnv, _head: Node, _args: Vec<Node>) -> NodeResult {
     Ok(Node::new(Val::Environment(env), Loc::Unknown))
 }
@@ -434,6 +1058,184 @@ fn prim_readable_string(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> Nod
     Ok(Node::new(Val::StringVal(s), n.loc))
 }
 
+fn prim_doc(env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let arg = args.remove(0);
+    let loc = arg.loc.clone();
+    let doc = match documented_value(&env, arg)?.val {
+        Val::Routine(robj) => robj.doc,
+        Val::Primitive(PrimitiveObj { doc: Some(doc), .. }) => Some(doc.doc.to_string()),
+        _ => None,
+    };
+    Ok(match doc {
+        Some(doc) => Node::new(Val::StringVal(doc), loc),
+        None => Node::new(Val::Nil, loc),
+    })
+}
+
+// A list of lists, so that routines could one day have more than one
+fn prim_arglists(env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let arg = args.remove(0);
+    let loc = arg.loc.clone();
+    let arglist = match documented_value(&env, arg)?.val {
+        Val::Routine(robj) => Node::new(Val::List(robj.params), loc.clone()),
+        Val::Primitive(PrimitiveObj { doc: Some(doc), .. }) => {
+            match front::parse("<arglist>", doc.arglist) {
+                Ok(mut nodes) => nodes.remove(0),
+                Err(_) => return Ok(Node::new(Val::Nil, loc)),
+            }
+        }
+        _ => return Ok(Node::new(Val::Nil, loc)),
+    };
+    Ok(Node::new(Val::List(vec![arglist]), loc))
+}
+
+fn prim_meta(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let arg = args.remove(0);
+    Ok(match arg.meta {
+        Some(meta) => Node::new(meta.val, arg.loc),
+        None => Node::new(Val::Nil, arg.loc),
+    })
+}
+
+fn prim_with_meta(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let mut target = args.remove(0);
+    let meta = args.remove(0);
+    target.meta = match meta.val {
+        Val::Nil => None,
+        Val::List(ref entries) if entries.len() % 2 == 0 => Some(Box::new(meta.clone())),
+        v => {
+            return Err(RuntimeError::UnexpectedValue(
+                "list of keys and values".to_string(),
+                v,
+                meta.loc,
+            ))
+        }
+    };
+    Ok(target)
+}
+
+// The value itself, or the one bound to it if it is a symbol
+fn documented_value(env: &SmartEnv, arg: Node) -> NodeResult {
+    match arg.val {
+        Val::Symbol(name) => match env.borrow().get(&name) {
+            Some(node) => Ok(node),
+            None => Err(RuntimeError::UndefinedName(name, arg.loc)),
+        },
+        _ => Ok(arg),
+    }
+}
+
+// (is value) and (is value message) fail unless the value is true
+fn prim_is(_env: SmartEnv, head: Node, args: Vec<Node>) -> NodeResult {
+    if args[0].as_host_boolean()? {
+        return Ok(Node::new(Val::Boolean(true), head.loc));
+    }
+
+    let message = match args.get(1) {
+        Some(message) => message.as_print_friendly_string(),
+        None => format!("Expected a true value, but got: {}", args[0].val),
+    };
+    Err(RuntimeError::AssertionFailed(message, head.loc))
+}
+
+fn prim_assert_equal(_env: SmartEnv, head: Node, args: Vec<Node>) -> NodeResult {
+    let (expected, actual) = (&args[0].val, &args[1].val);
+    if expected == actual {
+        return Ok(Node::new(Val::Boolean(true), head.loc));
+    }
+
+    Err(RuntimeError::AssertionFailed(
+        format!("Expected {}, but got: {}", expected, actual),
+        head.loc,
+    ))
+}
+
+fn prim_use_fixture(env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let fixture = args.remove(0);
+    match fixture.val {
+        Val::Routine(..) | Val::Primitive(..) => {}
+        v => {
+            return Err(RuntimeError::UnexpectedValue(
+                "function".to_string(),
+                v,
+                fixture.loc,
+            ))
+        }
+    }
+
+    let loc = fixture.loc.clone();
+    env.borrow().context.add_fixture(fixture);
+    Ok(Node::new(Val::Nil, loc))
+}
+
+// (rand-int n) is from 0 up to n, and (rand-int low high) is from low up to high, excluding n
+// and high
+fn prim_rand_int(env: SmartEnv, head: Node, args: Vec<Node>) -> NodeResult {
+    let (low, high) = match args.len() {
+        1 => (0, args[0].as_host_number()?),
+        _ => (args[0].as_host_number()?, args[1].as_host_number()?),
+    };
+    if high <= low {
+        let bound = &args[args.len() - 1];
+        return Err(RuntimeError::UnexpectedValue(
+            format!("number greater than {}", low),
+            bound.val.clone(),
+            bound.loc.clone(),
+        ));
+    }
+
+    let range = (i64::from(high) - i64::from(low)) as u64;
+    let n = i64::from(low) + env.borrow().context.next_random_below(range) as i64;
+    Ok(Node::new(Val::Number(n as i32), head.loc))
+}
+
+fn prim_rand_seed(env: SmartEnv, head: Node, args: Vec<Node>) -> NodeResult {
+    let seed = args[0].as_host_number()?;
+    env.borrow().context.seed_random(i64::from(seed) as u64);
+    Ok(Node::new(Val::Nil, head.loc))
+}
+
+// A list or string with the same elements in a random order
+fn prim_shuffle(env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
+    let n = args.remove(0);
+    let loc = n.loc.clone();
+    let (mut elements, is_string) = match n.val {
+        Val::Nil => return Ok(Node::new(Val::Nil, loc)),
+        Val::List(children) => (children, false),
+        Val::StringVal(s) => (
+            s.chars()
+                .map(|c| Node::new(Val::Character(c.to_string()), loc.clone()))
+                .collect(),
+            true,
+        ),
+        v => {
+            return Err(RuntimeError::UnexpectedArgumentType {
+                procedure_name: "shuffle".to_string(),
+                expected_type_name: "list or string".to_string(),
+                actual_val: v,
+                loc,
+            })
+        }
+    };
+
+    // Fisher-Yates, which makes every order equally likely
+    let context = Rc::clone(&env.borrow().context);
+    for i in (1..elements.len()).rev() {
+        let j = context.next_random_below(i as u64 + 1) as usize;
+        elements.swap(i, j);
+    }
+
+    if is_string {
+        let s: String = elements
+            .iter()
+            .map(|c| c.as_print_friendly_string())
+            .collect();
+        Ok(Node::new(Val::StringVal(s), loc))
+    } else {
+        Ok(Node::new(Val::List(elements), loc))
+    }
+}
+
 fn prim_cell(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
     let n = args.remove(0);
     let loc = n.loc.clone();
@@ -463,6 +1265,55 @@ fn prim_get_cell(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult
     }
 }
 
+fn prim_break(env: SmartEnv, head: Node, _args: Vec<Node>) -> NodeResult {
+    debugger::prompt(&env, &head.loc, "break")?;
+    Ok(Node::new(Val::Nil, head.loc))
+}
+
+// (trace name ...) traces the calls of the routines or primitives with the names, returning the
+// names of all that are traced
+fn prim_trace(env: SmartEnv, head: Node, args: Vec<Node>) -> NodeResult {
+    let context = Rc::clone(&env.borrow().context);
+    for name in trace_names("trace", args)? {
+        context.trace(&name);
+    }
+    Ok(traced_names(&context.traced(), head.loc))
+}
+
+// (untrace name ...) stops tracing the names, and (untrace) stops tracing everything
+fn prim_untrace(env: SmartEnv, head: Node, args: Vec<Node>) -> NodeResult {
+    let context = Rc::clone(&env.borrow().context);
+    if args.is_empty() {
+        context.untrace_all();
+    }
+    for name in trace_names("untrace", args)? {
+        context.untrace(&name);
+    }
+    Ok(traced_names(&context.traced(), head.loc))
+}
+
+fn trace_names(procedure_name: &str, args: Vec<Node>) -> Result<Vec<String>, RuntimeError> {
+    args.into_iter()
+        .map(|arg| match arg.val {
+            Val::Symbol(name) => Ok(name),
+            v => Err(RuntimeError::UnexpectedArgumentType {
+                procedure_name: procedure_name.to_string(),
+                expected_type_name: "symbol".to_string(),
+                actual_val: v,
+                loc: arg.loc,
+            }),
+        })
+        .collect()
+}
+
+fn traced_names(names: &[String], loc: Loc) -> Node {
+    let symbols = names
+        .iter()
+        .map(|name| Node::new(Val::Symbol(name.clone()), loc.clone()))
+        .collect();
+    Node::new(Val::List(symbols), loc)
+}
+
 fn prim_host_inspect(_env: SmartEnv, _head: Node, mut args: Vec<Node>) -> NodeResult {
     let n = args.remove(0);
     println!("{:?}", n);
@@ -480,3 +1331,27 @@ fn prim_host_backtrace(_env: SmartEnv, _head: Node, _args: Vec<Node>) -> NodeRes
 
     Ok(Node::new(Val::Nil, Loc::Unknown))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_primitive_is_documented() {
+        let env = ::test_env();
+        let mut undocumented: Vec<String> = env
+            .borrow()
+            .map
+            .iter()
+            .filter_map(|(name, node)| match node.val {
+                Val::Primitive(PrimitiveObj { doc: None, .. }) => Some(name.clone()),
+                _ => None,
+            }).collect();
+        undocumented.sort();
+        assert_eq!(undocumented, Vec::<String>::new());
+
+        for &(_, arglist, _) in PRIMITIVE_DOCS {
+            assert!(front::parse("<arglist>", arglist).is_ok(), "{}", arglist);
+        }
+    }
+}
@@ -20,6 +20,12 @@ pub enum RuntimeError {
         actual_val: Val,
         loc: Loc,
     },
+    UnexpectedReturnType {
+        procedure_name: String,
+        expected_type_name: String,
+        actual_val: Val,
+        loc: Loc,
+    },
     CannotUpdateElementInValue(Val, Loc),
     IndexOutOfBounds {
         index: usize,
@@ -52,6 +58,19 @@ pub enum RuntimeError {
     CannotConsNonCharacterOntoString(Val, Loc),
     CannotGetLengthOfNonCollection(Val, Loc),
     SyntaxErrorDuringRead(String, SyntaxError, Loc),
+    IoFailure {
+        operation: String,
+        path: String,
+        message: String,
+        loc: Loc,
+    },
+    LimitExceeded {
+        limit_name: String,
+        limit: u64,
+        loc: Loc,
+    },
+    Interrupted(Loc),
+    AssertionFailed(String, Loc),
 }
 
 impl RuntimeError {
@@ -84,6 +103,16 @@ impl RuntimeError {
                 expected_type_name,
                 actual_val,
             ),
+            UnexpectedReturnType {
+                procedure_name,
+                expected_type_name,
+                actual_val, ..
+            } => format!(
+                "Procedure '{}' expected to return a value of type '{}', but returned: {}",
+                procedure_name,
+                expected_type_name,
+                actual_val,
+            ),
             CannotUpdateElementInValue(val, _) => format!("Cannot update an element in: {}", val),
             IndexOutOfBounds { index, len, .. } => {
                 format!("Index of {} is out of bounds of length {}", index, len)
@@ -140,6 +169,10 @@ impl RuntimeError {
             CannotConsNonCharacterOntoString(val, _) => format!("Cannot cons non-character onto a string: {}", val),
             CannotGetLengthOfNonCollection(val, _) => format!("Cannot get length of a non-collection: {}", val),
             SyntaxErrorDuringRead(s, syntax_error, _) => format!("Unable to read string \"{}\": {}", s, syntax_error.display()),
+            IoFailure { operation, path, message, .. } => format!("Unable to {} '{}': {}", operation, path, message),
+            LimitExceeded { limit_name, limit, .. } => format!("Exceeded the limit of {} for {}", limit, limit_name),
+            Interrupted(_) => "Evaluation interrupted".to_string(),
+            AssertionFailed(message, _) => format!("Assertion failed: {}", message),
         }
     }
 
@@ -156,6 +189,7 @@ impl RuntimeError {
             UnableToEvalListStartingWith(_, l) => l.clone(),
             UnexpectedValue(_, _, l) => l.clone(),
             UnexpectedArgumentType { loc, .. } => loc.clone(),
+            UnexpectedReturnType { loc, .. } => loc.clone(),
             CannotUpdateElementInValue(_, l) => l.clone(),
             IndexOutOfBounds { loc, .. } => loc.clone(),
             NotEnoughArgs(.., loc) => loc.clone(),
@@ -173,6 +207,49 @@ impl RuntimeError {
             CannotConsNonCharacterOntoString(.., loc) => loc.clone(),
             CannotGetLengthOfNonCollection(.., loc) => loc.clone(),
             SyntaxErrorDuringRead(.., loc) => loc.clone(),
+            IoFailure { loc, .. } => loc.clone(),
+            LimitExceeded { loc, .. } => loc.clone(),
+            Interrupted(loc) => loc.clone(),
+            AssertionFailed(_, loc) => loc.clone(),
+        }
+    }
+
+    // A code identifying the kind of error, for tools. Codes are never reused or renumbered.
+    pub fn code(&self) -> &'static str {
+        use self::RuntimeError::*;
+        match self {
+            Unknown(..) => "E0101",
+            UndefinedPrimitive(..) => "E0102",
+            UndefinedName(..) => "E0103",
+            CannotRedefine(..) => "E0104",
+            CannotEvalEmptyList(..) => "E0105",
+            CannotUpdateUndefinedName(..) => "E0106",
+            UnableToEvalValue(..) => "E0107",
+            UnableToEvalListStartingWith(..) => "E0108",
+            UnexpectedValue(..) => "E0109",
+            UnexpectedArgumentType { .. } => "E0110",
+            CannotUpdateElementInValue(..) => "E0111",
+            IndexOutOfBounds { .. } => "E0112",
+            NotEnoughArgs(..) => "E0113",
+            WrongNumberOfArgs(..) => "E0114",
+            ArgCountOutOfRange(..) => "E0115",
+            ParamsMustBeSymbols(..) => "E0116",
+            CondUnmatchedClause(..) => "E0117",
+            ApplicationPanic(..) => "E0118",
+            CannotInvokeNonProcedure(..) => "E0119",
+            FunctionArgsDoNotMatchParams { .. } => "E0120",
+            TooManyFunctionParamsAfterRest { .. } => "E0121",
+            CannotAppendOnto(..) => "E0122",
+            CannotGetChildrenOfNonCollection(..) => "E0123",
+            CannotConsOntoNonCollection(..) => "E0124",
+            CannotConsNonCharacterOntoString(..) => "E0125",
+            CannotGetLengthOfNonCollection(..) => "E0126",
+            SyntaxErrorDuringRead(..) => "E0127",
+            IoFailure { .. } => "E0128",
+            LimitExceeded { .. } => "E0129",
+            Interrupted(..) => "E0130",
+            AssertionFailed(..) => "E0131",
+            UnexpectedReturnType { .. } => "E0132",
         }
     }
 }
@@ -0,0 +1,177 @@
+use back::context::Limits;
+
+// Primitives that cannot touch the file system, processes, environment variables, the host's
+// standard input, or the host's debugging facilities
+pub const SAFE_PRIMITIVES: &[&str] = &[
+    "+",
+    "-",
+    "=",
+    "<",
+    ">",
+    "panic",
+    "print",
+    "println",
+    "eprint",
+    "eprintln",
+    "flush",
+    "not",
+    "apply",
+    "typeof",
+    "str",
+    "concat",
+    "cons",
+    "first",
+    "rest",
+    "len",
+    "trim-string",
+    "path-join",
+    "path-split",
+    "current-environment",
+    "eval",
+    "read-string",
+    "readable-string",
+    "doc",
+    "arglists",
+    "meta",
+    "with-meta",
+    "is",
+    "assert=",
+    "use-fixture",
+    "rand-int",
+    "rand-seed!",
+    "shuffle",
+    "trace",
+    "untrace",
+    "cell",
+    "set-cell!",
+    "get-cell",
+];
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct SandboxConfig {
+    pub allowed_primitives: Vec<String>,
+    pub limits: Limits,
+}
+
+impl SandboxConfig {
+    pub fn allows_primitive(&self, name: &str) -> bool {
+        self.allowed_primitives
+            .iter()
+            .any(|allowed| allowed == name)
+    }
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        SandboxConfig {
+            allowed_primitives: SAFE_PRIMITIVES.iter().map(|s| s.to_string()).collect(),
+            limits: Limits {
+                max_steps: Some(1_000_000),
+                max_depth: Some(64),
+                max_allocations: Some(10_000_000),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::WriterObj;
+    use back;
+    use back::env::SmartEnv;
+
+    fn sandboxed_env(config: SandboxConfig) -> SmartEnv {
+        back::create_sandboxed_root_env(WriterObj::Sink, ::test_reader(), config).unwrap()
+    }
+
+    #[test]
+    fn test_denied_primitives_are_not_installed() {
+        let env = sandboxed_env(SandboxConfig::default());
+
+        let output = ::parse_eval_print(env, "sandbox", "(load \"examples/prelude.mn\")");
+
+        assert_eq!(
+            output,
+            "Runtime error (sandbox:1:2): Undefined name: load\n  |\n1 | (load \"examples/prelude.mn\")\n  |  ^^^^\n"
+        );
+    }
+
+    #[test]
+    fn test_allowed_primitives_are_installed() {
+        let env = sandboxed_env(SandboxConfig::default());
+
+        let output = ::parse_eval_print(env, "sandbox", "(+ 1 2)");
+
+        assert_eq!(output, "3");
+    }
+
+    #[test]
+    fn test_step_limit_stops_infinite_loop() {
+        let env = sandboxed_env(SandboxConfig::default());
+
+        let output = ::parse_eval_print(env, "sandbox", "(def loop (fn () (loop)))\n(loop)");
+
+        assert_eq!(
+            output,
+            "Runtime error (sandbox:1:18): Exceeded the limit of 1000000 for evaluation steps\n  |\n1 | (def loop (fn () (loop)))\n  |                  ^^^^^^\n"
+        );
+    }
+
+    #[test]
+    fn test_depth_limit_stops_deep_recursion() {
+        let env = sandboxed_env(SandboxConfig::default());
+
+        let output = ::parse_eval_print(env, "sandbox", "(def f (fn (n) (+ 1 (f n))))\n(f 0)");
+
+        assert_eq!(
+            output,
+            "Runtime error (sandbox:1:22): Exceeded the limit of 64 for nesting depth\n  |\n1 | (def f (fn (n) (+ 1 (f n))))\n  |                      ^\n"
+        );
+    }
+
+    #[test]
+    fn test_allocation_limit() {
+        let mut config = SandboxConfig::default();
+        config.limits.max_allocations = Some(5);
+        let env = sandboxed_env(config);
+
+        let output = ::parse_eval_print(env, "sandbox", "(str \"abc\" \"def\")");
+
+        assert_eq!(
+            output,
+            "Runtime error (sandbox:1:12): Exceeded the limit of 5 for total allocations\n  |\n1 | (str \"abc\" \"def\")\n  |            ^^^^^\n"
+        );
+    }
+
+    #[test]
+    fn test_allocation_limit_counts_nested_values() {
+        let mut config = SandboxConfig::default();
+        config.limits.max_allocations = Some(100);
+        let env = sandboxed_env(config);
+
+        let output = ::parse_eval_print(
+            env,
+            "sandbox",
+            "(def row '(0 1 2 3 4 5 6 7 8 9))\n\
+             (def grow (fn (rows n) (if (= n 0) rows (grow (cons row rows) (- n 1)))))\n\
+             (def table (grow nil 10))",
+        );
+
+        assert!(output.contains("Exceeded the limit of 100 for total allocations"));
+    }
+
+    #[test]
+    fn test_env_is_usable_after_limit_exceeded() {
+        let env = sandboxed_env(SandboxConfig::default());
+
+        ::parse_eval_print(
+            ::std::rc::Rc::clone(&env),
+            "sandbox",
+            "(def loop (fn () (loop)))\n(loop)",
+        );
+        let output = ::parse_eval_print(env, "sandbox", "(+ 1 2)");
+
+        assert_eq!(output, "3");
+    }
+}
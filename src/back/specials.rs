@@ -1,10 +1,12 @@
-use ast::{Node, RoutineObj, RoutineType, Val};
+use ast::{Node, RoutineObj, RoutineType, Val, WriterObj, TYPE_NAMES};
+use back::context::TestDef;
 use back::env::{Env, SmartEnv};
 use back::eval;
 use back::runtime_error::RuntimeError;
 use back::trampoline;
 use back::trampoline::{ContinuationResult, Flag};
 use loc::Loc;
+use std::cell::RefCell;
 use std::rc::Rc;
 
 pub fn eval_special_list(env: SmartEnv, loc: Loc, args: Vec<Node>) -> ContinuationResult {
@@ -34,6 +36,13 @@ pub fn eval_special_def(env: SmartEnv, mut args: Vec<Node>) -> ContinuationResul
             _ => (),
         }
 
+        // Metadata on the name, as in (def ^:private x 1), is about what it names
+        if let Some(meta) = name_node.meta {
+            if let Val::List(ref entries) = meta.val {
+                value_node = value_node.add_meta(entries, meta.loc.clone());
+            }
+        }
+
         env.borrow_mut().define(&name, value_node)?;
         Ok(trampoline::finish(Node::new(Val::Nil, name_node.loc))) // TODO: should be nil
     } else {
@@ -166,8 +175,11 @@ pub fn eval_special_for(env: SmartEnv, mut args: Vec<Node>) -> ContinuationResul
     let body = args.remove(0);
 
     let mut output = Node::new(Val::Nil, loc.clone());
+    let context = Rc::clone(&env.borrow().context);
 
     while start_number <= end_number {
+        context.check_interrupt(&loc)?;
+
         let loop_env = Env::new(Some(Rc::clone(&env)));
         let index_node = Node::new(Val::Number(start_number), loc.clone());
         loop_env.borrow_mut().define(&name, index_node)?;
@@ -189,25 +201,81 @@ pub fn eval_special_begin(env: SmartEnv, unevaled_args: Vec<Node>) -> Continuati
     ))
 }
 
+// Temporarily rebinds *writer* to a buffer wherever it is defined, so that output written by
+// routines called from the body is captured too, and then restores it even if the body fails.
+pub fn eval_special_with_output_to_string(
+    env: SmartEnv,
+    loc: Loc,
+    unevaled_args: Vec<Node>,
+) -> ContinuationResult {
+    let original_writer = match env.borrow().get("*writer*") {
+        Some(node) => node,
+        None => return Err(RuntimeError::UndefinedName("*writer*".to_string(), loc)),
+    };
+
+    let buffer = Rc::new(RefCell::new(Vec::<u8>::new()));
+    let buffer_writer = Node::new(
+        Val::Writer(WriterObj::Buffer(Rc::clone(&buffer))),
+        loc.clone(),
+    );
+    env.borrow_mut().update("*writer*", buffer_writer)?;
+
+    let body_result = trampoline::run(
+        eval::eval_each_node_in_list_for_single_output,
+        Rc::clone(&env),
+        Node::new(Val::List(unevaled_args), loc.clone()),
+    );
+
+    env.borrow_mut().update("*writer*", original_writer)?;
+    body_result?;
+
+    let output = String::from_utf8_lossy(&buffer.borrow()).to_string();
+    Ok(trampoline::finish(Node::new(Val::StringVal(output), loc)))
+}
+
 pub fn eval_special_routine(
     lexical_env: SmartEnv,
     mut args: Vec<Node>,
     routine_type: RoutineType,
 ) -> ContinuationResult {
     let param_list = args.remove(0);
+    // A string between the params and the body documents the routine
+    let doc = if args.len() > 1 {
+        let doc_node = args.remove(0);
+        match doc_node.val {
+            Val::StringVal(doc) => Some(doc),
+            v => {
+                return Err(RuntimeError::UnexpectedValue(
+                    "documentation string".to_string(),
+                    v,
+                    doc_node.loc,
+                ))
+            }
+        }
+    } else {
+        None
+    };
     let body = args.remove(0); // The body is only one node
+    let return_type = annotated_type(&param_list)?;
 
     match param_list.val {
-        Val::List(children) => Ok(trampoline::finish(Node::new(
-            Val::Routine(RoutineObj {
-                name: None,
-                params: children,
-                body: Box::new(body),
-                lexical_env: Rc::clone(&lexical_env),
-                routine_type,
-            }),
-            param_list.loc,
-        ))),
+        Val::List(children) => {
+            for param in &children {
+                annotated_type(param)?;
+            }
+            Ok(trampoline::finish(Node::new(
+                Val::Routine(RoutineObj {
+                    name: None,
+                    params: children,
+                    body: Box::new(body),
+                    lexical_env: Rc::clone(&lexical_env),
+                    routine_type,
+                    doc,
+                    return_type,
+                }),
+                param_list.loc,
+            )))
+        }
         _ => Err(RuntimeError::UnexpectedValue(
             "list of parameters".to_string(),
             param_list.val,
@@ -216,6 +284,22 @@ pub fn eval_special_routine(
     }
 }
 
+// The type that a param, or a list of params, is annotated with, which must be one that exists
+fn annotated_type(node: &Node) -> Result<Option<String>, RuntimeError> {
+    match node.meta_get(":tag") {
+        Some(&Node {
+            val: Val::Symbol(ref type_name),
+            ..
+        }) if TYPE_NAMES.contains(&type_name.as_str()) => Ok(Some(type_name.clone())),
+        Some(tag) => Err(RuntimeError::UnexpectedValue(
+            "type".to_string(),
+            tag.val.clone(),
+            tag.loc.clone(),
+        )),
+        None => Ok(None),
+    }
+}
+
 pub fn eval_special_macroexpand1(env: SmartEnv, mut args: Vec<Node>) -> ContinuationResult {
     let unexpanded_node = trampoline::run(eval::eval_node, Rc::clone(&env), args.remove(0))?;
 
@@ -236,3 +320,64 @@ pub fn eval_special_macroexpand1(env: SmartEnv, mut args: Vec<Node>) -> Continua
         )),
     }
 }
+
+// Registers the test with the context, for a test runner to run later. The test's name may be a
+// string or a symbol.
+pub fn eval_special_deftest(env: SmartEnv, loc: Loc, mut args: Vec<Node>) -> ContinuationResult {
+    let name_node = args.remove(0);
+    let name = match name_node.val {
+        Val::StringVal(name) | Val::Symbol(name) => name,
+        v => {
+            return Err(RuntimeError::UnexpectedValue(
+                "test name".to_string(),
+                v,
+                name_node.loc,
+            ))
+        }
+    };
+
+    let mut body = vec![Node::new(Val::Symbol("begin".to_string()), loc.clone())];
+    body.extend(args);
+    let routine = Node::new(
+        Val::Routine(RoutineObj {
+            name: Some(name.clone()),
+            params: Vec::new(),
+            body: Box::new(Node::new(Val::List(body), loc.clone())),
+            lexical_env: Rc::clone(&env),
+            routine_type: RoutineType::Function,
+            doc: None,
+            return_type: None,
+        }),
+        loc.clone(),
+    );
+
+    let context = Rc::clone(&env.borrow().context);
+    context.add_test(TestDef {
+        name,
+        routine,
+        loc: loc.clone(),
+    });
+    Ok(trampoline::finish(Node::new(Val::Nil, loc)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    #[test]
+    fn test_with_output_to_string_restores_writer_after_error() {
+        let (env, buffer) = ::test_env_with_buffer(::test_reader());
+        let failed = ::parse_eval_print(
+            Rc::clone(&env),
+            "t.mn",
+            "(with-output-to-string (println \"lost\") (panic \"inner\"))",
+        );
+        assert!(failed.contains("Application Panic: inner"));
+
+        ::parse_eval_print(env, "t.mn", "(println \"writer restored\")");
+        assert_eq!(
+            String::from_utf8_lossy(&buffer.borrow()),
+            "writer restored\n"
+        );
+    }
+}
@@ -1,7 +1,9 @@
 use ast::Node;
+use back::context::EvalContext;
 use back::env::SmartEnv;
-use back::eval::NodeResult;
+use back::eval::{NodeResult, RoutineReturn};
 use back::runtime_error::RuntimeError;
+use std::rc::Rc;
 
 pub enum Flag {
     None,
@@ -14,8 +16,11 @@ pub type ContinuationResult = Result<Continuation, RuntimeError>;
 
 // If it contains a Next, the thunk is the next computation to execute.
 // If it contains a Node, the trampolining session is over and the Node represents the result.
+// A NextThen is like a Next, but once the rest of the chain has a result, the RoutineReturn is
+// applied to it. The chain continues in the same trampoline, so that tail calls stay tail calls.
 pub enum Continuation {
     Next(Thunk, SmartEnv, Node, Vec<Node>, Flag),
+    NextThen(Thunk, SmartEnv, Node, RoutineReturn),
     Outcome(Node),
 }
 
@@ -33,6 +38,10 @@ pub fn bounce_with_nodes(
     Continuation::Next(t, e, n, ns, flag)
 }
 
+pub fn bounce_then(t: Thunk, e: SmartEnv, n: Node, ret: RoutineReturn) -> Continuation {
+    Continuation::NextThen(t, e, n, ret)
+}
+
 pub fn finish(n: Node) -> Continuation {
     Continuation::Outcome(n)
 }
@@ -52,13 +61,40 @@ pub fn run_with_flag(t: Thunk, e: SmartEnv, n: Node, flag: Flag) -> NodeResult {
 // The trampoline iteratively calls a chain of thunks until there is no next thunk,
 // at which point it pulls the resulting Node out of the continuation and returns it.
 fn run_with_everything(t: Thunk, e: SmartEnv, n: Node, ns: Vec<Node>, flag: Flag) -> NodeResult {
+    let context = Rc::clone(&e.borrow().context);
+
+    context.enter(&n.loc)?;
+    let result = run_thunks(&context, t, e, n, ns, flag);
+    if result.is_err() {
+        context.record_error_stack();
+    }
+    context.exit();
+
+    result
+}
+
+fn run_thunks(
+    context: &EvalContext,
+    t: Thunk,
+    e: SmartEnv,
+    n: Node,
+    ns: Vec<Node>,
+    flag: Flag,
+) -> NodeResult {
     let mut current_t = t;
     let mut current_e = e;
     let mut current_n = n;
     let mut current_ns = ns;
     let mut current_flag = flag;
-    loop {
-        let k = current_t(current_e, current_n, current_ns, current_flag)?;
+    let mut returns: Vec<RoutineReturn> = Vec::new();
+    let result = loop {
+        if let Err(e) = context.step(&current_n.loc) {
+            break Err(e);
+        }
+        let k = match current_t(current_e, current_n, current_ns, current_flag) {
+            Ok(k) => k,
+            Err(e) => break Err(e),
+        };
         match k {
             Continuation::Next(next_t, next_e, next_n, next_ns, next_flag) => {
                 current_t = next_t;
@@ -67,7 +103,26 @@ fn run_with_everything(t: Thunk, e: SmartEnv, n: Node, ns: Vec<Node>, flag: Flag
                 current_ns = next_ns;
                 current_flag = next_flag;
             }
-            Continuation::Outcome(n) => return Ok(n),
+            Continuation::NextThen(next_t, next_e, next_n, ret) => {
+                // A return that would always succeed or fail along with the newer one is
+                // redundant, so that a loop of tail calls doesn't pile them up
+                if returns.last().is_some_and(|last| ret.supersedes(last)) {
+                    returns.pop();
+                }
+                returns.push(ret);
+                current_t = next_t;
+                current_e = next_e;
+                current_n = next_n;
+                current_ns = Vec::new();
+                current_flag = Flag::None;
+            }
+            Continuation::Outcome(n) => break context.allocate(&n).map(|()| n),
         }
-    }
+    };
+
+    // The newest return is that of the innermost call, which returns first
+    returns
+        .into_iter()
+        .rev()
+        .fold(result, |result, ret| ret.apply(result))
 }
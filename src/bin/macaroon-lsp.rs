@@ -0,0 +1,15 @@
+extern crate macaroon;
+
+use std::io;
+use std::process;
+
+// Speaks the Language Server Protocol over stdin and stdout
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+
+    if let Err(e) = macaroon::lsp::run(stdin.lock(), stdout.lock()) {
+        eprintln!("macaroon-lsp: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,815 @@
+/* Static analysis of source code, finding mistakes that would otherwise only be found when the
+code runs: undefined names, calls with the wrong number of arguments, cond forms with a test but
+no result, bindings that are never used, and definitions that shadow builtins.
+
+Types are checked gradually: where the params of a routine or primitive are annotated with types,
+as in (fn ^number (^number a ^number b) ...), arguments whose types can be told without running
+them are checked against them, as is the body of a routine annotated with the type it returns.
+The types of primitives come from the annotations in their arglists.
+
+Nothing is evaluated except top-level definitions, so that calls to macros (such as defn from
+the prelude) can be expanded and the expansions checked. They are evaluated in a sandboxed
+environment. Files loaded at the top level contribute their definitions, but are not checked
+themselves. */
+
+use ast::{type_satisfies, Node, ReaderObj, Val, WriterObj, TYPE_NAMES};
+use back;
+use back::env::SmartEnv;
+use back::eval::{routine_arity, SPECIAL_FORMS};
+use back::runtime_error::{check_args, RuntimeError};
+use back::sandbox::SandboxConfig;
+use front;
+use loc::Loc;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub loc: Loc,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn display(&self) -> String {
+        match self.loc {
+            Loc::File {
+                ref filename,
+                line,
+                column,
+                ..
+            } => format!(
+                "Warning ({}:{}:{}): {}",
+                filename, line, column, self.message
+            ),
+            Loc::Unknown => format!("Warning: {}", self.message),
+        }
+    }
+}
+
+// What is known about a name defined at the top level
+#[derive(Clone, Debug, PartialEq)]
+enum Known {
+    Routine {
+        min_arity: isize,
+        max_arity: isize,
+        signature: Signature,
+    },
+    Macro,
+    Value,
+}
+
+// The types that a routine or primitive is annotated to take and return, where it has them
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Signature {
+    params: Vec<Option<String>>,
+    rest: Option<String>,
+    returns: Option<String>,
+}
+
+impl Signature {
+    fn of(params: &Node) -> Signature {
+        let mut signature = Signature {
+            returns: annotation(params),
+            ..Signature::default()
+        };
+        if let Val::List(ref params) = params.val {
+            match params.iter().position(|p| is_symbol(p, "&rest")) {
+                Some(i) => {
+                    signature.params = params[..i].iter().map(annotation).collect();
+                    signature.rest = params.get(i + 1).and_then(annotation);
+                }
+                None => signature.params = params.iter().map(annotation).collect(),
+            }
+        }
+        signature
+    }
+
+    fn param(&self, i: usize) -> Option<&String> {
+        match self.params.get(i) {
+            Some(param) => param.as_ref(),
+            None => self.rest.as_ref(),
+        }
+    }
+}
+
+// The type the node is annotated with. An annotation that isn't a type is warned about instead.
+fn annotation(node: &Node) -> Option<String> {
+    match node.type_annotation() {
+        Some(type_name) if TYPE_NAMES.contains(&type_name) => Some(type_name.to_string()),
+        _ => None,
+    }
+}
+
+struct Binding {
+    name: String,
+    loc: Loc,
+    used: bool,
+    type_name: Option<String>,
+}
+
+struct Checker {
+    builtins: SmartEnv,
+    expander: SmartEnv,
+    globals: HashMap<String, Known>,
+    scopes: Vec<Vec<Binding>>,
+    loaded: HashSet<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+fn reader_function() -> Result<String, String> {
+    Err("Input cannot be read while checking".to_string())
+}
+
+// Returns the problems found in the source, or the formatted syntax errors that prevent it from
+// being parsed
+pub fn check_source(filename: &str, input: &str) -> Result<Vec<Diagnostic>, String> {
+    let nodes = match front::parse(filename, input) {
+        Ok(nodes) => nodes,
+        Err(errors) => return Err(::format_syntax_errors_in_source(&errors, input)),
+    };
+
+    let mut checker = match Checker::new() {
+        Ok(checker) => checker,
+        Err(e) => return Err(::format_runtime_error(&e)),
+    };
+
+    let nodes = checker.learn_definitions(nodes);
+    for node in &nodes {
+        checker.check(node);
+    }
+
+    checker.diagnostics.sort_by_key(|d| match d.loc {
+        Loc::File { line, column, .. } => (line, column),
+        Loc::Unknown => (0, 0),
+    });
+    Ok(checker.diagnostics)
+}
+
+impl Checker {
+    fn new() -> Result<Checker, RuntimeError> {
+        let reader = ReaderObj { reader_function };
+        Ok(Checker {
+            builtins: back::create_root_env(WriterObj::Sink, reader.clone())?,
+            expander: back::create_sandboxed_root_env(
+                WriterObj::Sink,
+                reader,
+                SandboxConfig::default(),
+            )?,
+            globals: HashMap::new(),
+            scopes: Vec::new(),
+            loaded: HashSet::new(),
+            diagnostics: Vec::new(),
+        })
+    }
+
+    fn warn(&mut self, loc: &Loc, message: String) {
+        self.diagnostics.push(Diagnostic {
+            loc: loc.clone(),
+            message,
+        });
+    }
+
+    // Records the top-level definitions, in order, following loads. Returns the top-level
+    // nodes with their macros expanded.
+    fn learn_definitions(&mut self, nodes: Vec<Node>) -> Vec<Node> {
+        let mut expanded_nodes = Vec::new();
+
+        for node in nodes {
+            let node = match self.expand(&node) {
+                Some(expanded) => expanded,
+                None => node,
+            };
+
+            match node.val {
+                Val::List(ref children)
+                    if children.len() == 3 && is_symbol(&children[0], "def") =>
+                {
+                    if let Val::Symbol(ref name) = children[1].val {
+                        // Errors will be found when the definition itself is checked
+                        let _ = back::eval(Rc::clone(&self.expander), vec![node.clone()]);
+                        self.globals
+                            .insert(name.clone(), routine_shape(&children[2]));
+                    }
+                }
+                Val::List(ref children)
+                    if children.len() == 2 && is_symbol(&children[0], "load") =>
+                {
+                    if let Val::StringVal(ref path) = children[1].val {
+                        self.load(path, &node.loc);
+                    }
+                }
+                _ => {}
+            }
+
+            expanded_nodes.push(node);
+        }
+
+        expanded_nodes
+    }
+
+    fn load(&mut self, path: &str, loc: &Loc) {
+        if !self.loaded.insert(path.to_string()) {
+            return;
+        }
+
+        let input = match fs::read_to_string(path) {
+            Ok(input) => input,
+            Err(e) => {
+                self.warn(loc, format!("Unable to load file '{}': {}", path, e));
+                return;
+            }
+        };
+        match front::parse(path, &input) {
+            Ok(nodes) => {
+                self.learn_definitions(nodes);
+            }
+            Err(_) => self.warn(
+                loc,
+                format!("Unable to load file '{}': syntax errors", path),
+            ),
+        }
+    }
+
+    // Expands the node if it is a call to a known macro that isn't shadowed by a local binding
+    fn expand(&mut self, node: &Node) -> Option<Node> {
+        let name = match node.val {
+            Val::List(ref children) if !children.is_empty() => match children[0].val {
+                Val::Symbol(ref name) => name.clone(),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        if self.is_local(&name) || self.globals.get(&name) != Some(&Known::Macro) {
+            return None;
+        }
+
+        match back::macroexpand(Rc::clone(&self.expander), node.clone()) {
+            Ok(expanded) => Some(relocate(expanded, &node.loc)),
+            Err(e) => {
+                let loc = node.loc.clone();
+                self.warn(
+                    &loc,
+                    format!("Unable to expand macro '{}': {}", name, e.display()),
+                );
+                Some(Node::new(Val::Nil, loc))
+            }
+        }
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.scopes
+            .iter()
+            .any(|scope| scope.iter().any(|b| b.name == name))
+    }
+
+    fn is_builtin(&self, name: &str) -> bool {
+        SPECIAL_FORMS.contains(&name) || self.builtins.borrow().get(name).is_some()
+    }
+
+    // Marks the innermost binding of the name as used, returning whether there was one
+    fn use_local(&mut self, name: &str) -> bool {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(binding) = scope.iter_mut().rev().find(|b| b.name == name) {
+                binding.used = true;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn resolve(&mut self, name: &str, loc: &Loc) {
+        if self.use_local(name) || self.globals.contains_key(name) || self.is_builtin(name) {
+            return;
+        }
+        self.warn(loc, format!("Undefined name: {}", name));
+    }
+
+    fn bind(&mut self, name: &str, loc: &Loc) {
+        if self.is_builtin(name) {
+            self.warn(loc, format!("Binding of '{}' shadows a builtin", name));
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(Binding {
+                name: name.to_string(),
+                loc: loc.clone(),
+                used: false,
+                type_name: None,
+            });
+        }
+    }
+
+    // Gives the latest binding of the name in the innermost scope a type
+    fn set_local_type(&mut self, name: &str, type_name: Option<String>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            if let Some(binding) = scope.iter_mut().rev().find(|b| b.name == name) {
+                binding.type_name = type_name;
+            }
+        }
+    }
+
+    fn local_type(&self, name: &str) -> Option<String> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.iter().rev().find(|b| b.name == name))
+            .and_then(|b| b.type_name.clone())
+    }
+
+    fn signature_of(&self, name: &str) -> Option<Signature> {
+        if self.is_local(name) {
+            return None;
+        }
+        match self.globals.get(name) {
+            Some(Known::Routine { signature, .. }) => Some(signature.clone()),
+            Some(..) => None,
+            None => match self.builtins.borrow().get(name).map(|n| n.val) {
+                Some(Val::Primitive(p)) => p
+                    .doc
+                    .and_then(|doc| front::parse("<arglist>", doc.arglist).ok())
+                    .and_then(|mut nodes| nodes.pop())
+                    .map(|params| Signature::of(&params)),
+                _ => None,
+            },
+        }
+    }
+
+    // The type of value the node evaluates to, where it can be told without evaluating it
+    fn infer(&self, node: &Node) -> Option<String> {
+        let children = match node.val {
+            Val::Symbol(ref name) => return self.local_type(name),
+            Val::List(ref children) if !children.is_empty() => children,
+            ref v => return v.type_name().ok(),
+        };
+        let name = match children[0].val {
+            Val::Symbol(ref name) if !self.is_local(name) => name.as_str(),
+            _ => return None,
+        };
+
+        match name {
+            "quote" if children.len() == 2 => children[1].val.type_name().ok(),
+            "fn" | "macro" => Some("function".to_string()),
+            "list" => Some("list".to_string()),
+            "begin" => self.infer(&children[children.len() - 1]),
+            "if" if children.len() == 4 => {
+                let (then, otherwise) = (self.infer(&children[2]), self.infer(&children[3]));
+                if then == otherwise {
+                    then
+                } else {
+                    None
+                }
+            }
+            _ if SPECIAL_FORMS.contains(&name) => None,
+            _ => self.signature_of(name).and_then(|s| s.returns),
+        }
+    }
+
+    // Warns about a type annotation that doesn't name a type
+    fn check_annotation(&mut self, node: &Node) {
+        if let Some(type_name) = node.type_annotation() {
+            if !TYPE_NAMES.contains(&type_name) {
+                let loc = node
+                    .meta
+                    .as_ref()
+                    .map_or(node.loc.clone(), |m| m.loc.clone());
+                self.warn(&loc, format!("Unknown type: {}", type_name));
+            }
+        }
+    }
+
+    // Warns if the body of a function is of another type than the one its params are annotated
+    // to return
+    fn check_return_type(&mut self, params: &Node, body: &Node) {
+        let expected = match annotation(params) {
+            Some(ref expected) if expected == "any" => return,
+            Some(expected) => expected,
+            None => return,
+        };
+        match self.infer(body) {
+            Some(ref actual) if !type_satisfies(actual, &expected) => self.warn(
+                &body.loc,
+                format!(
+                    "Function annotated to return type '{}' returns type '{}'",
+                    expected, actual
+                ),
+            ),
+            _ => {}
+        }
+    }
+
+    fn check_arg_types(&mut self, name: &str, args: &[Node]) {
+        let signature = match self.signature_of(name) {
+            Some(signature) => signature,
+            None => return,
+        };
+        for (i, arg) in args.iter().enumerate() {
+            let expected = match signature.param(i) {
+                Some(expected) if expected != "any" => expected,
+                _ => continue,
+            };
+            match self.infer(arg) {
+                Some(ref actual) if !type_satisfies(actual, expected) => self.warn(
+                    &arg.loc,
+                    format!(
+                        "'{}' expects argument {} of type '{}', but got type '{}'",
+                        name,
+                        i + 1,
+                        expected,
+                        actual
+                    ),
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn pop_scope(&mut self) {
+        if let Some(scope) = self.scopes.pop() {
+            for binding in scope {
+                if !binding.used && !binding.name.starts_with('_') {
+                    self.warn(&binding.loc, format!("Unused binding: {}", binding.name));
+                }
+            }
+        }
+    }
+
+    fn check(&mut self, node: &Node) {
+        match node.val {
+            Val::Symbol(ref name) => self.resolve(name, &node.loc),
+            Val::List(ref children) if !children.is_empty() => self.check_list(node, children),
+            _ => {}
+        }
+    }
+
+    fn check_all(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            self.check(node);
+        }
+    }
+
+    fn check_list(&mut self, node: &Node, children: &[Node]) {
+        let loc = &node.loc;
+        let args = &children[1..];
+        let name = match children[0].val {
+            Val::Symbol(ref name) if !self.is_local(name) => name.clone(),
+            _ => return self.check_all(children),
+        };
+
+        if let Some(expanded) = self.expand(node) {
+            return self.check(&expanded);
+        }
+
+        if SPECIAL_FORMS.contains(&name.as_ref()) {
+            if let Some((min, max)) = special_form_arity(&name, args) {
+                if let Err(e) = check_args(&name, loc, &args.to_vec(), min, max) {
+                    return self.warn(loc, e.display());
+                }
+            }
+            return self.check_special_form(&name, args);
+        }
+
+        self.resolve(&name, &children[0].loc);
+        self.check_all(args);
+
+        let arity = match self.globals.get(&name) {
+            Some(&Known::Routine {
+                min_arity,
+                max_arity,
+                ..
+            }) => Some((min_arity, max_arity)),
+            Some(..) => None,
+            None => match self.builtins.borrow().get(&name).map(|n| n.val) {
+                Some(Val::Primitive(p)) => Some((p.min_arity, p.max_arity)),
+                _ => None,
+            },
+        };
+        if let Some((min, max)) = arity {
+            if let Err(e) = check_args(&name, loc, &args.to_vec(), min, max) {
+                return self.warn(loc, e.display());
+            }
+        }
+        self.check_arg_types(&name, args);
+    }
+
+    fn check_special_form(&mut self, name: &str, args: &[Node]) {
+        match name {
+            "quote" => {}
+            "def" => {
+                if let Val::Symbol(ref defined) = args[0].val {
+                    if self.scopes.is_empty() {
+                        if self.is_builtin(defined) {
+                            self.warn(
+                                &args[0].loc,
+                                format!("Definition of '{}' shadows a builtin", defined),
+                            );
+                        }
+                    } else {
+                        self.bind(defined, &args[0].loc);
+                        // A local definition is commonly only used by itself, recursively
+                        self.use_local(defined);
+                    }
+                }
+                self.check(&args[1]);
+            }
+            "fn" | "macro" => {
+                self.push_scope();
+                match args[0].val {
+                    Val::List(ref params) => {
+                        for param in params {
+                            self.check_annotation(param);
+                            match param.val {
+                                Val::Symbol(ref p) if p == "&rest" => {}
+                                Val::Symbol(ref p) => {
+                                    self.bind(p, &param.loc);
+                                    // The args of a macro are forms, whatever they're annotated with
+                                    if name == "fn" {
+                                        self.set_local_type(p, annotation(param));
+                                    }
+                                }
+                                ref v => self.warn(
+                                    &param.loc,
+                                    RuntimeError::ParamsMustBeSymbols(v.clone(), Loc::Unknown)
+                                        .display(),
+                                ),
+                            }
+                        }
+                    }
+                    ref v => self.warn(
+                        &args[0].loc,
+                        RuntimeError::ParamsMustBeSymbols(v.clone(), Loc::Unknown).display(),
+                    ),
+                }
+                let body = &args[args.len() - 1];
+                self.check(body);
+                self.check_annotation(&args[0]);
+                if name == "fn" {
+                    self.check_return_type(&args[0], body);
+                }
+                self.pop_scope();
+            }
+            "let" => {
+                self.push_scope();
+                let bindings = match args[0].val {
+                    Val::List(ref bindings) => bindings.clone(),
+                    _ => Vec::new(),
+                };
+                // Every binding is visible to every value, which allows recursive definitions
+                for pair in bindings.chunks(2) {
+                    if let Val::Symbol(ref bound) = pair[0].val {
+                        self.bind(bound, &pair[0].loc);
+                    }
+                }
+                for pair in bindings.chunks(2) {
+                    if pair.len() == 2 {
+                        self.check(&pair[1]);
+                    }
+                }
+                for pair in bindings.chunks(2) {
+                    if let (Val::Symbol(ref bound), Some(value)) = (&pair[0].val, pair.get(1)) {
+                        let type_name = self.infer(value);
+                        self.set_local_type(bound, type_name);
+                    }
+                }
+                self.check_all(&args[1..]);
+                self.pop_scope();
+            }
+            "cond" => {
+                if args.len() % 2 == 1 {
+                    let unmatched = &args[args.len() - 1];
+                    self.warn(
+                        &unmatched.loc,
+                        RuntimeError::CondUnmatchedClause(unmatched.val.clone(), Loc::Unknown)
+                            .display(),
+                    );
+                }
+                self.check_all(args);
+            }
+            "for" => {
+                self.check_all(&args[1..3]);
+                self.push_scope();
+                if let Val::Symbol(ref index) = args[0].val {
+                    self.bind(index, &args[0].loc);
+                    // Loops are often run only for their effects
+                    self.use_local(index);
+                }
+                self.check(&args[3]);
+                self.pop_scope();
+            }
+            "update!" => {
+                if let Val::Symbol(ref updated) = args[0].val {
+                    self.resolve(updated, &args[0].loc);
+                }
+                self.check(&args[1]);
+            }
+            // The name of a test may be a symbol, which isn't evaluated
+            "deftest" => self.check_all(&args[1..]),
+            _ => self.check_all(args),
+        }
+    }
+}
+
+// The arities enforced by eval_list, for the special forms whose arguments are checked here
+fn special_form_arity(name: &str, args: &[Node]) -> Option<(isize, isize)> {
+    match name {
+        "def" | "update!" => Some((2, 2)),
+        "fn" | "macro" => Some(routine_arity(args)),
+        "let" | "cond" => Some((2, -1)),
+        "deftest" => Some((1, -1)),
+        "for" => Some((4, 4)),
+        _ => None,
+    }
+}
+
+fn is_symbol(node: &Node, name: &str) -> bool {
+    match node.val {
+        Val::Symbol(ref s) => s == name,
+        _ => false,
+    }
+}
+
+// The arity of the routine that the unevaluated value would produce, if it is a routine
+fn routine_shape(value: &Node) -> Known {
+    let children = match value.val {
+        // A routine may have a documentation string between its params and its body
+        Val::List(ref children) if children.len() == 3 || children.len() == 4 => children,
+        _ => return Known::Value,
+    };
+    let params = match children[1].val {
+        Val::List(ref params) => params,
+        _ => return Known::Value,
+    };
+
+    if is_symbol(&children[0], "macro") {
+        return Known::Macro;
+    }
+    if !is_symbol(&children[0], "fn") {
+        return Known::Value;
+    }
+
+    let signature = Signature::of(&children[1]);
+    match params.iter().position(|p| is_symbol(p, "&rest")) {
+        Some(i) => Known::Routine {
+            min_arity: i as isize,
+            max_arity: -1,
+            signature,
+        },
+        None => Known::Routine {
+            min_arity: params.len() as isize,
+            max_arity: params.len() as isize,
+            signature,
+        },
+    }
+}
+
+// Nodes built by a macro have no location of their own, so they are given the call's location.
+// Their metadata, such as type annotations, is kept.
+fn relocate(node: Node, loc: &Loc) -> Node {
+    let node_loc = match node.loc {
+        Loc::Unknown => loc.clone(),
+        l => l,
+    };
+    let val = match node.val {
+        Val::List(children) => Val::List(children.into_iter().map(|c| relocate(c, loc)).collect()),
+        v => v,
+    };
+    Node {
+        meta: node.meta,
+        ..Node::new(val, node_loc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(input: &str) -> Vec<String> {
+        check_source("test.mn", input)
+            .unwrap()
+            .iter()
+            .map(|d| d.display())
+            .collect()
+    }
+
+    #[test]
+    fn test_undefined_names_and_arity() {
+        assert_eq!(
+            check("(def f (fn (a b) (+ a b)))\n(f 1)\n(g 2)\n(first 1 2)\n(f 1 2)"),
+            vec![
+                "Warning (test.mn:2:1): 'f' expects 2 arg(s), but got 1",
+                "Warning (test.mn:3:2): Undefined name: g",
+                "Warning (test.mn:4:1): 'first' expects 1 arg(s), but got 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scopes() {
+        assert_eq!(
+            check("(def f (fn (a _b unused) (let (x 1 y 2) (+ a x))))\n(f 1 2 3)"),
+            vec![
+                "Warning (test.mn:1:18): Unused binding: unused",
+                "Warning (test.mn:1:36): Unused binding: y",
+            ]
+        );
+        // Later top-level definitions are visible, and let bindings may refer to each other
+        assert_eq!(
+            check("(def f (fn () (g)))\n(def g (fn () (let (h (fn () (h))) (h))))"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_cond_and_shadowing() {
+        assert_eq!(
+            check("(def first 1)\n(def f (fn (list) (cond (= list 1) 2\n3)))\n(f 1)"),
+            vec![
+                "Warning (test.mn:1:6): Definition of 'first' shadows a builtin",
+                "Warning (test.mn:2:13): Binding of 'list' shadows a builtin",
+                "Warning (test.mn:3:1): 'cond' expects each clause to have a test and a body, but found: 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_documented_routines() {
+        assert_eq!(
+            check("(def f (fn (x) \"Doubles x\" (+ x x)))\n(f 1 2)\n(def g (fn () 1 2))\n(g)"),
+            vec![
+                "Warning (test.mn:2:1): 'f' expects 1 arg(s), but got 2",
+                "Warning (test.mn:3:8): 'fn' expects 2 arg(s), but got 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_types() {
+        assert_eq!(
+            check(
+                "(def add (fn ^number (^number a ^number b) (+ a b)))\n\
+                 (add 1 \"2\")\n\
+                 (add (add 1 2) (str 3))\n\
+                 (+ 'x (len \"abc\"))\n\
+                 (def f (fn ^string (^numbr n) (let (m (+ n 1)) (if (< m 0) \"-\" m))))\n\
+                 (def g (fn ^string (^string s) (+ s 1)))\n\
+                 (def h (fn ^any (x &rest ^number _xs) (if x \"a\" 'b)))\n\
+                 (f 1)\n\
+                 (g \"a\")\n\
+                 (h 1 2 \"3\")"
+            ),
+            vec![
+                "Warning (test.mn:2:8): 'add' expects argument 2 of type 'number', but got type 'string'",
+                "Warning (test.mn:3:16): 'add' expects argument 2 of type 'number', but got type 'string'",
+                "Warning (test.mn:4:4): '+' expects argument 1 of type 'number', but got type 'symbol'",
+                "Warning (test.mn:5:21): Unknown type: numbr",
+                "Warning (test.mn:6:32): Function annotated to return type 'string' returns type 'number'",
+                "Warning (test.mn:6:35): '+' expects argument 1 of type 'number', but got type 'string'",
+                "Warning (test.mn:10:8): 'h' expects argument 3 of type 'number', but got type 'string'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nil_is_a_list_and_primitives_are_functions() {
+        assert_eq!(
+            check(
+                "(def call (fn ^list (^function f ^list xs) (f xs)))\n\
+                 (call first nil)\n\
+                 (def none (fn ^list () nil))\n\
+                 (call 'first 1)"
+            ),
+            vec![
+                "Warning (test.mn:4:7): 'call' expects argument 1 of type 'function', but got type 'symbol'",
+                "Warning (test.mn:4:14): 'call' expects argument 2 of type 'list', but got type 'number'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_types_of_routines_defined_by_macros() {
+        assert_eq!(
+            check("(load \"examples/prelude.mn\")\n(defn twice ^number (^number x) (+ x x))\n(twice \"a\")"),
+            vec!["Warning (test.mn:3:8): 'twice' expects argument 1 of type 'number', but got type 'string'"]
+        );
+    }
+
+    #[test]
+    fn test_macros_from_loaded_files_are_expanded() {
+        assert_eq!(
+            check("(load \"examples/prelude.mn\")\n(defn f (x) (+ x y))\n(f 1 2)\n(map f '(1 2))"),
+            vec![
+                "Warning (test.mn:2:18): Undefined name: y",
+                "Warning (test.mn:3:1): 'f' expects 1 arg(s), but got 2",
+            ]
+        );
+        assert_eq!(
+            check("(load \"no/such/file.mn\")"),
+            vec![
+                "Warning (test.mn:1:1): Unable to load file 'no/such/file.mn': No such file or directory (os error 2)",
+            ]
+        );
+    }
+}
@@ -0,0 +1,161 @@
+/* Line coverage of Macaroon code, for --coverage. While coverage is enabled in a root
+environment, every evaluation of a node from a file counts as a hit on the line the node starts
+on. The hits can then be written as LCOV, the format read by most coverage tools. */
+
+use ast::{Node, Val};
+use front;
+use loc::Loc;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Coverage {
+    // The number of hits on each line of each file, by filename
+    hits: BTreeMap<String, BTreeMap<i32, u64>>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage {
+            hits: BTreeMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, loc: &Loc) {
+        if let Loc::File {
+            ref filename, line, ..
+        } = *loc
+        {
+            *self
+                .hits
+                .entry(filename.clone())
+                .or_default()
+                .entry(line)
+                .or_insert(0) += 1;
+        }
+    }
+
+    // Adds the hits of the other coverage, such as that of another environment, to these
+    pub fn merge(&mut self, other: &Coverage) {
+        for (filename, lines) in &other.hits {
+            let merged = self
+                .hits
+                .entry(filename.clone())
+                .or_default();
+            for (line, hits) in lines {
+                *merged.entry(*line).or_insert(0) += hits;
+            }
+        }
+    }
+
+    pub fn hits(&self, filename: &str, line: i32) -> u64 {
+        self.hits
+            .get(filename)
+            .and_then(|lines| lines.get(&line))
+            .map_or(0, |hits| *hits)
+    }
+
+    // A record for each file with hits that can still be read. Lines of code that were never
+    // evaluated are found by parsing the file again, and are reported with no hits.
+    pub fn to_lcov(&self) -> String {
+        let mut output = String::from("TN:\n");
+        for (filename, lines) in &self.hits {
+            let source = match fs::read_to_string(filename) {
+                Ok(source) => source,
+                Err(_) => continue,
+            };
+
+            let mut code_lines: BTreeSet<i32> = lines.keys().cloned().collect();
+            if let Ok(nodes) = front::parse(filename, &source) {
+                for node in &nodes {
+                    add_code_lines(node, &mut code_lines);
+                }
+            }
+
+            output.push_str(&format!("SF:{}\n", filename));
+            for line in &code_lines {
+                output.push_str(&format!("DA:{},{}\n", line, self.hits(filename, *line)));
+            }
+            let hit_count = code_lines
+                .iter()
+                .filter(|line| self.hits(filename, **line) > 0)
+                .count();
+            output.push_str(&format!("LH:{}\n", hit_count));
+            output.push_str(&format!("LF:{}\n", code_lines.len()));
+            output.push_str("end_of_record\n");
+        }
+        output
+    }
+}
+
+// The lines that nodes which could be evaluated start on. Quoted values are data, unless a
+// macro makes code of them, in which case their lines have hits anyway.
+fn add_code_lines(node: &Node, lines: &mut BTreeSet<i32>) {
+    if let Loc::File { line, .. } = node.loc {
+        lines.insert(line);
+    }
+    if let Val::List(ref children) = node.val {
+        match children.first() {
+            Some(&Node {
+                val: Val::Symbol(ref name),
+                ..
+            }) if name == "quote" => {}
+            _ => {
+                for child in children {
+                    add_code_lines(child, lines);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use back;
+    use std::env;
+
+    #[test]
+    fn test_lcov_reports_lines_never_evaluated() {
+        let path = env::temp_dir().join("macaroon-coverage.mn");
+        let path = path.to_str().unwrap();
+        let source = "(def f (fn (x)\n  (if (= x 1)\n    'one\n    (list x\n      'other))))\n(f 1)\n'(a\n  b)";
+        fs::write(path, source).unwrap();
+
+        let env = ::test_env();
+        back::enable_coverage(&env);
+        let input = format!("(load \"{}\")", path);
+        assert_eq!(::parse_eval_print(env.clone(), "REPL", &input), "nil");
+        let lcov = back::take_coverage(&env).unwrap().to_lcov();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            lcov,
+            format!(
+                "TN:\nSF:{}\nDA:1,2\nDA:2,5\nDA:3,1\nDA:4,0\nDA:5,0\nDA:6,3\nDA:7,1\nLH:5\nLF:7\nend_of_record\n",
+                path
+            )
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let loc = |line| Loc::File {
+            filename: "a.mn".to_string(),
+            line,
+            column: 1,
+            end_line: line,
+            end_column: 2,
+        };
+        let mut first = Coverage::new();
+        first.record(&loc(1));
+        first.record(&Loc::Unknown);
+        let mut second = Coverage::new();
+        second.record(&loc(1));
+        second.record(&loc(2));
+
+        first.merge(&second);
+        assert_eq!((first.hits("a.mn", 1), first.hits("a.mn", 2)), (2, 1));
+        assert_eq!(first.hits("b.mn", 1), 0);
+    }
+}
@@ -0,0 +1,579 @@
+/* An interactive debugger for Macaroon code. While debugging is enabled in a root environment,
+the evaluation stops before forms that reach a breakpoint, or that a step asked to stop at, and
+takes commands at a prompt. The prompt reads from *reader* and writes to *writer*, so it works
+wherever the code being debugged can read input.
+
+Anything typed at the prompt that isn't a command is evaluated in the environment of the form
+stopped at, so that its bindings can be inspected and changed. (break) opens the same prompt from
+within the code, whether or not debugging was enabled. */
+
+use ast::{Node, Val};
+use back::env::{Env, SmartEnv};
+use back::runtime_error::RuntimeError;
+use loc::Loc;
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::rc::{Rc, Weak};
+
+const HELP: &str = "\
+:continue, :c     Continue until a breakpoint is reached
+:step, :s         Stop at the next form evaluated
+:next, :n         Stop at the next form after this one, without stopping in routines it calls
+:break [SPEC]     Stop at FILE:LINE, or on entering the routine named SPEC, or list breakpoints
+:delete SPEC      Remove a breakpoint
+:where            Show the form stopped at
+:backtrace, :bt   List the evaluations in progress, innermost first
+:env              List the bindings of each environment the form can see, innermost first
+:quit             End the evaluation with an error
+:help             Show this help
+Anything else is evaluated in the environment of the form stopped at.
+";
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Breakpoint {
+    Line { filename: String, line: i32 },
+    Routine(String),
+}
+
+impl Breakpoint {
+    // FILE:LINE when what follows the last colon is a number, or else the name of a routine
+    pub fn parse(spec: &str) -> Breakpoint {
+        if let Some(i) = spec.rfind(':') {
+            if let Ok(line) = spec[i + 1..].parse::<i32>() {
+                return Breakpoint::Line {
+                    filename: spec[..i].to_string(),
+                    line,
+                };
+            }
+        }
+        Breakpoint::Routine(spec.to_string())
+    }
+
+    // A breakpoint's filename may leave out the directories of the file's path
+    fn is_at(&self, filename: &str, line: i32) -> bool {
+        match *self {
+            Breakpoint::Line {
+                filename: ref f,
+                line: l,
+            } => l == line && (filename == f || filename.ends_with(&format!("/{}", f))),
+            Breakpoint::Routine(..) => false,
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Breakpoint::Line { ref filename, line } => write!(f, "{}:{}", filename, line),
+            Breakpoint::Routine(ref name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Mode {
+    Continue,
+    Step,
+    // Stops at the next form outside the one stopped at that is evaluated in the same
+    // environment, or in a trampoline no more deeply nested, unless a routine was entered in
+    // that trampoline since
+    Next {
+        form: Loc,
+        env: Weak<RefCell<Env>>,
+        depth: usize,
+        entered: bool,
+    },
+}
+
+#[derive(Debug)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    mode: Mode,
+    // The line of the most recent form evaluated, so that a breakpoint stops once each time its
+    // line is reached, rather than at every form on it
+    last_line: Option<(String, i32)>,
+    // Set on entering a routine with a breakpoint, to stop at the first form of its body
+    routine_stop: Option<String>,
+    // How many prompts are open. Evaluations typed at a prompt never stop.
+    prompts: usize,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: Vec::new(),
+            mode: Mode::Continue,
+            last_line: None,
+            routine_stop: None,
+            prompts: 0,
+        }
+    }
+
+    // Returns false if there already was such a breakpoint
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        if self.breakpoints.contains(&breakpoint) {
+            return false;
+        }
+        self.breakpoints.push(breakpoint);
+        true
+    }
+
+    // Returns false if there was no such breakpoint
+    pub fn remove_breakpoint(&mut self, breakpoint: &Breakpoint) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|b| b != breakpoint);
+        self.breakpoints.len() != count
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn step(&mut self) {
+        self.mode = Mode::Step;
+    }
+
+    fn next(&mut self, form: &Loc, env: &SmartEnv, depth: usize) {
+        self.mode = Mode::Next {
+            form: form.clone(),
+            env: Rc::downgrade(env),
+            depth,
+            entered: false,
+        };
+    }
+
+    fn resume(&mut self) {
+        self.mode = Mode::Continue;
+    }
+
+    // Why the evaluation should stop before evaluating the node, if it should. Only forms from
+    // files are stopped at.
+    pub fn should_stop(&mut self, node: &Node, env: &SmartEnv, depth: usize) -> Option<String> {
+        let (filename, line) = match (&node.val, &node.loc) {
+            (
+                &Val::List(..),
+                &Loc::File {
+                    ref filename, line, ..
+                },
+            ) => (filename, line),
+            _ => return None,
+        };
+        if self.prompts > 0 {
+            return None;
+        }
+
+        let arrived = self
+            .last_line
+            .as_ref()
+            .is_none_or(|(f, l)| f != filename || *l != line);
+        if arrived {
+            self.last_line = Some((filename.clone(), line));
+        }
+
+        if let Some(name) = self.routine_stop.take() {
+            return Some(format!("breakpoint {}", name));
+        }
+        if arrived {
+            if let Some(b) = self.breakpoints.iter().find(|b| b.is_at(filename, line)) {
+                return Some(format!("breakpoint {}", b));
+            }
+        }
+
+        match self.mode {
+            Mode::Continue => None,
+            Mode::Step => Some("step".to_string()),
+            Mode::Next {
+                ref form,
+                env: ref next_env,
+                depth: next_depth,
+                entered,
+            } => {
+                let same_env = next_env.upgrade().is_some_and(|e| Rc::ptr_eq(&e, env));
+                if !is_inside(&node.loc, form) && (same_env || (depth <= next_depth && !entered)) {
+                    Some("next".to_string())
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    // Called as a function is entered, in the trampoline at the depth
+    pub fn enter_routine(&mut self, name: Option<&str>, depth: usize) {
+        if self.prompts > 0 {
+            return;
+        }
+        if let Mode::Next {
+            depth: ref mut next_depth,
+            ref mut entered,
+            ..
+        } = self.mode
+        {
+            if depth <= *next_depth {
+                *next_depth = depth;
+                *entered = true;
+            }
+        }
+        if let Some(name) = name {
+            if self
+                .breakpoints
+                .contains(&Breakpoint::Routine(name.to_string()))
+            {
+                self.routine_stop = Some(name.to_string());
+            }
+        }
+    }
+
+    // Called as the trampoline at the depth finishes
+    pub fn leave_depth(&mut self, depth: usize) {
+        if let Mode::Next {
+            depth: next_depth,
+            ref mut entered,
+            ..
+        } = self.mode
+        {
+            if depth <= next_depth {
+                *entered = false;
+            }
+        }
+    }
+}
+
+// Whether the inner span is within the outer one, without being the same span
+fn is_inside(inner: &Loc, outer: &Loc) -> bool {
+    match (inner, outer) {
+        (
+            &Loc::File {
+                filename: ref f1,
+                line: l1,
+                column: c1,
+                end_line: el1,
+                end_column: ec1,
+            },
+            &Loc::File {
+                filename: ref f2,
+                line: l2,
+                column: c2,
+                end_line: el2,
+                end_column: ec2,
+            },
+        ) => f1 == f2 && inner != outer && (l2, c2) <= (l1, c1) && (el1, ec1) <= (el2, ec2),
+        _ => false,
+    }
+}
+
+// Stops the evaluation at the location, taking commands until one of them continues it
+pub fn prompt(env: &SmartEnv, loc: &Loc, reason: &str) -> Result<(), RuntimeError> {
+    let context = Rc::clone(&env.borrow().context);
+    context.with_debugger(|d| d.prompts += 1);
+    let result = run_prompt(env, loc, reason);
+    context.with_debugger(|d| d.prompts -= 1);
+    result
+}
+
+fn run_prompt(env: &SmartEnv, loc: &Loc, reason: &str) -> Result<(), RuntimeError> {
+    let context = Rc::clone(&env.borrow().context);
+    write(env, &describe_stop(loc, reason), loc)?;
+
+    loop {
+        write(env, "debug> ", loc)?;
+        let input = match read_input(env) {
+            Some(input) => input,
+            None => {
+                // With no more input, there is nobody left to take commands from
+                context.with_debugger(|d| d.resume());
+                write(env, "\n", loc)?;
+                return Ok(());
+            }
+        };
+
+        let trimmed = input.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if !trimmed.starts_with(':') {
+            let output = ::parse_eval_print(Rc::clone(env), "<debug>", trimmed);
+            write(env, &format!("{}\n", output.trim_end()), loc)?;
+            continue;
+        }
+
+        let command_line = &trimmed[1..];
+        let (command, argument) = match command_line.find(char::is_whitespace) {
+            Some(i) => (&command_line[..i], command_line[i..].trim()),
+            None => (command_line, ""),
+        };
+        let output = match (command, argument) {
+            ("continue", _) | ("c", _) => {
+                context.with_debugger(|d| d.resume());
+                return Ok(());
+            }
+            ("step", _) | ("s", _) => {
+                context.with_debugger(|d| d.step());
+                return Ok(());
+            }
+            ("next", _) | ("n", _) => {
+                let depth = context.depth();
+                context.with_debugger(|d| d.next(loc, env, depth));
+                return Ok(());
+            }
+            ("quit", _) | ("q", _) => {
+                context.with_debugger(|d| d.resume());
+                return Err(RuntimeError::Interrupted(loc.clone()));
+            }
+            ("break", "") | ("b", "") => {
+                let specs: Vec<String> = context
+                    .with_debugger(|d| d.breakpoints().iter().map(|b| b.to_string()).collect());
+                if specs.is_empty() {
+                    "No breakpoints".to_string()
+                } else {
+                    specs.join("\n")
+                }
+            }
+            ("break", spec) | ("b", spec) => {
+                let breakpoint = Breakpoint::parse(spec);
+                if context.with_debugger(|d| d.add_breakpoint(breakpoint)) {
+                    format!("Breakpoint {}", spec)
+                } else {
+                    format!("Already a breakpoint: {}", spec)
+                }
+            }
+            ("delete", spec) if !spec.is_empty() => {
+                let breakpoint = Breakpoint::parse(spec);
+                if context.with_debugger(|d| d.remove_breakpoint(&breakpoint)) {
+                    format!("Deleted breakpoint {}", spec)
+                } else {
+                    format!("No breakpoint {}", spec)
+                }
+            }
+            ("where", _) => describe_stop(loc, reason).trim_end().to_string(),
+            ("backtrace", _) | ("bt", _) => context
+                .frames()
+                .iter()
+                .rev()
+                .filter(|l| **l != Loc::Unknown)
+                .map(|l| format!("  at {}", describe_loc(l)))
+                .collect::<Vec<String>>()
+                .join("\n"),
+            ("env", _) => describe_envs(env),
+            ("help", _) => HELP.trim_end().to_string(),
+            ("delete", _) => "Missing argument for :delete. Type :help for usage.".to_string(),
+            _ => format!(
+                "Unknown command :{}. Type :help for a list of commands.",
+                command
+            ),
+        };
+        write(env, &format!("{}\n", output), loc)?;
+    }
+}
+
+fn describe_stop(loc: &Loc, reason: &str) -> String {
+    let snippet = match *loc {
+        Loc::File { ref filename, .. } => fs::read_to_string(filename)
+            .ok()
+            .and_then(|source| loc.snippet(&source)),
+        Loc::Unknown => None,
+    };
+    format!(
+        "Stopped at {} ({})\n{}",
+        describe_loc(loc),
+        reason,
+        snippet.unwrap_or_default()
+    )
+}
+
+fn describe_loc(loc: &Loc) -> String {
+    match *loc {
+        Loc::File {
+            ref filename,
+            line,
+            column,
+            ..
+        } => format!("{}:{}:{}", filename, line, column),
+        Loc::Unknown => "unknown location".to_string(),
+    }
+}
+
+// The bindings of each environment from the given one up to its root, whose bindings are only
+// counted, as they are usually the primitives and the definitions of the program
+fn describe_envs(env: &SmartEnv) -> String {
+    let mut lines = Vec::new();
+    let mut current = Some(Rc::clone(env));
+    let mut i = 0;
+    while let Some(e) = current {
+        let e = e.borrow();
+        match e.parent {
+            Some(..) => {
+                lines.push(format!("#{} {}", i, e.name));
+                let mut names: Vec<&String> = e.map.keys().collect();
+                names.sort();
+                for name in names {
+                    lines.push(format!("  {} = {}", name, e.map[name].val));
+                }
+            }
+            None => lines.push(format!("#{} {} ({} names)", i, e.name, e.map.len())),
+        }
+        current = e.parent.clone();
+        i += 1;
+    }
+    lines.join("\n")
+}
+
+fn write(env: &SmartEnv, s: &str, loc: &Loc) -> Result<(), RuntimeError> {
+    let writer = match env.borrow().get("*writer*") {
+        Some(Node {
+            val: Val::Writer(w),
+            ..
+        }) => w,
+        _ => {
+            return Err(RuntimeError::UndefinedName(
+                "*writer*".to_string(),
+                loc.clone(),
+            ))
+        }
+    };
+    writer
+        .write_str(s)
+        .and_then(|_| writer.flush())
+        .map_err(|e| RuntimeError::Unknown(format!("Problem while writing: {}", e), loc.clone()))
+}
+
+// Reads lines until they form complete input, returning None once there is no more to read
+fn read_input(env: &SmartEnv) -> Option<String> {
+    let reader_function = match env.borrow().get("*reader*") {
+        Some(Node {
+            val: Val::Reader(r),
+            ..
+        }) => r.reader_function,
+        _ => return None,
+    };
+
+    let mut input = String::new();
+    loop {
+        match reader_function() {
+            Ok(ref line) if !line.is_empty() => input.push_str(line),
+            _ => return None,
+        }
+        if !input.ends_with('\n') {
+            input.push('\n');
+        }
+        if !::is_input_incomplete(&input) {
+            return Some(input);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::ReaderObj;
+    use back;
+    use std::collections::VecDeque;
+    use std::env;
+
+    thread_local! {
+        static INPUT: RefCell<VecDeque<String>> = const { RefCell::new(VecDeque::new()) };
+    }
+
+    fn reader_function() -> Result<String, String> {
+        INPUT.with(|input| Ok(input.borrow_mut().pop_front().unwrap_or_default()))
+    }
+
+    // Runs the source as a file, typing each command at the prompts it stops at, and returns
+    // what was written with the file's path replaced by t.mn
+    fn debug(name: &str, source: &str, breakpoints: &[&str], commands: &[&str]) -> String {
+        let path = env::temp_dir().join(name);
+        let path = path.to_str().unwrap().to_string();
+        fs::write(&path, source).unwrap();
+        INPUT.with(|input| {
+            *input.borrow_mut() = commands.iter().map(|c| format!("{}\n", c)).collect();
+        });
+
+        let (env, buffer) = ::test_env_with_buffer(ReaderObj { reader_function });
+        back::enable_debugger(
+            &env,
+            breakpoints.iter().map(|b| Breakpoint::parse(b)).collect(),
+        );
+        let result = ::load_file(env, &path);
+        fs::remove_file(&path).unwrap();
+
+        let written = String::from_utf8_lossy(&buffer.borrow()).into_owned();
+        format!("{}{}", written, result).replace(&path, "t.mn")
+    }
+
+    #[test]
+    fn test_parse_breakpoints() {
+        assert_eq!(
+            Breakpoint::parse("src/a.mn:12"),
+            Breakpoint::Line {
+                filename: "src/a.mn".to_string(),
+                line: 12
+            }
+        );
+        assert_eq!(
+            Breakpoint::parse("count"),
+            Breakpoint::Routine("count".to_string())
+        );
+        assert!(Breakpoint::parse("a.mn:3").is_at("examples/a.mn", 3));
+        assert!(!Breakpoint::parse("a.mn:3").is_at("examples/ba.mn", 3));
+    }
+
+    #[test]
+    fn test_line_breakpoint_and_inspection() {
+        let output = debug(
+            "macaroon-debugger-line.mn",
+            "(def f (fn (x)\n  (+ x 1)))\n(f 41)",
+            &["macaroon-debugger-line.mn:2"],
+            &["x", "(def x 2)", ":env", ":bt", ":c"],
+        );
+        // The number of names in the root environment changes as primitives are added
+        assert!(output.starts_with(
+            "Stopped at t.mn:2:3 (breakpoint macaroon-debugger-line.mn:2)\n  |\n\
+             2 |   (+ x 1)))\n  |   ^^^^^^^\n\
+             debug> 41\n\
+             debug> nil\n\
+             debug> #0 Local\n  x = 2\n#1 TopLevel ("
+        ));
+        assert!(output.ends_with(" names)\ndebug>   at t.mn:3:1\ndebug> 3"));
+    }
+
+    #[test]
+    fn test_routine_breakpoint_step_and_next() {
+        let output = debug(
+            "macaroon-debugger-step.mn",
+            "(def g (fn (x) (+ x 1)))\n\
+             (def f (fn (x)\n  (begin\n    (print (g x))\n    (print \"!\"))))\n\
+             (f 1)\n(print \"end\")",
+            &["f"],
+            &[":s", ":n", ":s", ":n", ":n", ":n"],
+        );
+        assert_eq!(
+            output,
+            "Stopped at t.mn:3:3 (breakpoint f)\n  |\n3 |   (begin\n  |   ^^^^^^\n\
+             debug> Stopped at t.mn:4:5 (step)\n  |\n4 |     (print (g x))\n  |     ^^^^^^^^^^^^^\n\
+             debug> 2Stopped at t.mn:5:5 (next)\n  |\n5 |     (print \"!\"))))\n  |     ^^^^^^^^^^^\n\
+             debug> !Stopped at t.mn:7:1 (step)\n  |\n7 | (print \"end\")\n  | ^^^^^^^^^^^^^\n\
+             debug> endnil"
+        );
+    }
+
+    #[test]
+    fn test_break_opens_prompt_in_lexical_environment() {
+        let output = debug(
+            "macaroon-debugger-break.mn",
+            "(def f (fn (x)\n  (let (y (+ x 1))\n    (break)\n    y)))\n(f 1)",
+            &[],
+            &["(list x y)", ":quit"],
+        );
+        assert!(output.starts_with(
+            "Stopped at t.mn:3:5 (break)\n  |\n3 |     (break)\n  |     ^^^^^^^\ndebug> (1 2)\ndebug> "
+        ));
+        assert!(output.contains("Runtime error (t.mn:3:5): Evaluation interrupted"));
+    }
+}
@@ -0,0 +1,322 @@
+/* Reference pages for a module, for `macaroon doc`. A module is a source file, whose top-level
+definitions are what it provides: (def name value), (defn name (params) ...) and
+(defmacro name (params) ...), along with (def name (fn (params) ...)) and the same with macro.
+
+The file is only parsed, never evaluated, so only definitions written out in these forms are
+found. Names starting with an underscore, or marked ^:private, are private to the module and are
+left out. Names marked ^:deprecated are shown as deprecated. */
+
+use ast::{Node, Val};
+use front;
+use std::fs;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum EntryKind {
+    Function,
+    Macro,
+    Value,
+}
+
+impl EntryKind {
+    fn name(&self) -> &'static str {
+        match *self {
+            EntryKind::Function => "function",
+            EntryKind::Macro => "macro",
+            EntryKind::Value => "value",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct DocEntry {
+    pub name: String,
+    pub kind: EntryKind,
+    // How a call is written, as in "(map f l)", for routines
+    pub signature: Option<String>,
+    pub doc: Option<String>,
+    pub deprecated: bool,
+}
+
+// The public definitions of the file, in the order they are first defined. Fails with the
+// formatted error if the file can't be read or parsed.
+pub fn read_module(path: &str) -> Result<Vec<DocEntry>, String> {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => return Err(format!("Unable to read file '{}': {}\n", path, e)),
+    };
+    let nodes = match front::parse(path, &source) {
+        Ok(nodes) => nodes,
+        Err(errors) => return Err(::format_syntax_errors_in_source(&errors, &source)),
+    };
+
+    let mut entries: Vec<DocEntry> = Vec::new();
+    for entry in nodes.iter().filter_map(entry) {
+        if entry.name.starts_with('_') {
+            continue;
+        }
+        // A later definition replaces an earlier one, but keeps its place
+        match entries.iter().position(|e| e.name == entry.name) {
+            Some(i) => entries[i] = entry,
+            None => entries.push(entry),
+        }
+    }
+    Ok(entries)
+}
+
+fn entry(node: &Node) -> Option<DocEntry> {
+    let children = match node.val {
+        Val::List(ref children) if children.len() >= 3 => children,
+        _ => return None,
+    };
+    let (form, name) = match (&children[0].val, &children[1].val) {
+        (Val::Symbol(form), Val::Symbol(name)) => (form.as_str(), name.clone()),
+        _ => return None,
+    };
+    if has_flag(&children[1], ":private") {
+        return None;
+    }
+
+    let entry = match form {
+        "def" => Some(match children[2].val {
+            Val::List(ref routine) if routine.len() >= 3 => {
+                let kind = match routine[0].val {
+                    Val::Symbol(ref s) if s == "fn" => EntryKind::Function,
+                    Val::Symbol(ref s) if s == "macro" => EntryKind::Macro,
+                    _ => return Some(value_entry(name)),
+                };
+                // As with fn and macro, the body is a single form after the string
+                routine_entry(name, kind, &routine[1], &routine[2..], 2)
+            }
+            _ => value_entry(name),
+        }),
+        // defn has a body of any number of forms, and only a string followed by more of them
+        // documents it
+        "defn" => Some(routine_entry(
+            name,
+            EntryKind::Function,
+            &children[2],
+            &children[3..],
+            2,
+        )),
+        "defmacro" => Some(routine_entry(
+            name,
+            EntryKind::Macro,
+            &children[2],
+            &children[3..],
+            2,
+        )),
+        _ => None,
+    };
+    entry.map(|entry| DocEntry {
+        deprecated: has_flag(&children[1], ":deprecated"),
+        ..entry
+    })
+}
+
+// Whether the metadata has the key, with a value other than false or nil
+fn has_flag(node: &Node, key: &str) -> bool {
+    match node.meta_get(key) {
+        Some(&Node {
+            val: Val::Boolean(false),
+            ..
+        })
+        | Some(&Node { val: Val::Nil, .. })
+        | None => false,
+        Some(..) => true,
+    }
+}
+
+fn value_entry(name: String) -> DocEntry {
+    DocEntry {
+        name,
+        kind: EntryKind::Value,
+        signature: None,
+        doc: None,
+        deprecated: false,
+    }
+}
+
+// The doc is the first of the forms after the params, when it is a string and there are at
+// least as many forms as given
+fn routine_entry(
+    name: String,
+    kind: EntryKind,
+    params: &Node,
+    after_params: &[Node],
+    forms_with_doc: usize,
+) -> DocEntry {
+    let signature = match params.val {
+        Val::List(ref params) => {
+            let mut call = vec![name.clone()];
+            call.extend(params.iter().map(|p| format!("{}", p.val)));
+            Some(format!("({})", call.join(" ")))
+        }
+        _ => None,
+    };
+    let doc = match after_params.first() {
+        Some(&Node {
+            val: Val::StringVal(ref doc),
+            ..
+        }) if after_params.len() >= forms_with_doc => Some(doc.clone()),
+        _ => None,
+    };
+    DocEntry {
+        name,
+        kind,
+        signature,
+        doc,
+        deprecated: false,
+    }
+}
+
+pub fn to_markdown(title: &str, entries: &[DocEntry]) -> String {
+    let mut output = format!("# {}\n", title);
+    for entry in entries {
+        output.push_str(&format!("\n## `{}`\n\n", entry.name));
+        if let Some(ref signature) = entry.signature {
+            output.push_str(&format!("```macaroon\n{}\n```\n\n", signature));
+        }
+        output.push_str(&format!("*{}*\n", kind_description(entry)));
+        if let Some(ref doc) = entry.doc {
+            output.push_str(&format!("\n{}\n", doc));
+        }
+    }
+    output
+}
+
+pub fn to_html(title: &str, entries: &[DocEntry]) -> String {
+    let title = escape_html(title);
+    let mut output = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n",
+        title, title
+    );
+    for entry in entries {
+        let name = escape_html(&entry.name);
+        output.push_str(&format!(
+            "<section id=\"{}\">\n<h2><code>{}</code></h2>\n",
+            name, name
+        ));
+        if let Some(ref signature) = entry.signature {
+            output.push_str(&format!(
+                "<pre><code>{}</code></pre>\n",
+                escape_html(signature)
+            ));
+        }
+        output.push_str(&format!("<p><em>{}</em></p>\n", kind_description(entry)));
+        if let Some(ref doc) = entry.doc {
+            output.push_str(&format!("<p>{}</p>\n", escape_html(doc)));
+        }
+        output.push_str("</section>\n");
+    }
+    output.push_str("</body>\n</html>\n");
+    output
+}
+
+fn kind_description(entry: &DocEntry) -> String {
+    if entry.deprecated {
+        format!("{}, deprecated", entry.kind.name())
+    } else {
+        entry.kind.name().to_string()
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn entries(name: &str, source: &str) -> Result<Vec<DocEntry>, String> {
+        let path = env::temp_dir().join(name);
+        fs::write(&path, source).unwrap();
+        let entries = read_module(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        entries
+    }
+
+    #[test]
+    fn test_entries_of_each_kind_of_definition() {
+        let found = entries(
+            "macaroon-doc-entries.mn",
+            "(def limit 10)\n\
+             (def twice (fn (f x) \"Calls f twice\" (f (f x))))\n\
+             (defn _helper (x) x)\n\
+             (def ^:private secret 42)\n\
+             (defn ^{:deprecated true} old-greet (name) name)\n\
+             (defn greet (name) \"Greets someone\" (print \"hi \") (println name))\n\
+             (defn answer () \"forty-two\")\n\
+             (defmacro unless (c body) \"The opposite of if\" (list 'if c nil body))\n\
+             (def limit 20)",
+        ).unwrap();
+
+        assert_eq!(
+            found,
+            vec![
+                value_entry("limit".to_string()),
+                DocEntry {
+                    name: "twice".to_string(),
+                    kind: EntryKind::Function,
+                    signature: Some("(twice f x)".to_string()),
+                    doc: Some("Calls f twice".to_string()),
+                    deprecated: false,
+                },
+                DocEntry {
+                    name: "old-greet".to_string(),
+                    kind: EntryKind::Function,
+                    signature: Some("(old-greet name)".to_string()),
+                    doc: None,
+                    deprecated: true,
+                },
+                DocEntry {
+                    name: "greet".to_string(),
+                    kind: EntryKind::Function,
+                    signature: Some("(greet name)".to_string()),
+                    doc: Some("Greets someone".to_string()),
+                    deprecated: false,
+                },
+                DocEntry {
+                    name: "answer".to_string(),
+                    kind: EntryKind::Function,
+                    signature: Some("(answer)".to_string()),
+                    doc: None,
+                    deprecated: false,
+                },
+                DocEntry {
+                    name: "unless".to_string(),
+                    kind: EntryKind::Macro,
+                    signature: Some("(unless c body)".to_string()),
+                    doc: Some("The opposite of if".to_string()),
+                    deprecated: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_markdown_and_html() {
+        let entries = vec![
+            DocEntry {
+                name: "<=".to_string(),
+                kind: EntryKind::Function,
+                signature: Some("(<= a b)".to_string()),
+                doc: Some("Whether a <= b".to_string()),
+                deprecated: true,
+            },
+            value_entry("limit".to_string()),
+        ];
+
+        assert_eq!(
+            to_markdown("math.mn", &entries),
+            "# math.mn\n\n## `<=`\n\n```macaroon\n(<= a b)\n```\n\n*function, deprecated*\n\nWhether a <= b\n\n## `limit`\n\n*value*\n"
+        );
+        assert!(to_html("math.mn", &entries).contains(
+            "<section id=\"&lt;=\">\n<h2><code>&lt;=</code></h2>\n<pre><code>(&lt;= a b)</code></pre>\n<p><em>function, deprecated</em></p>\n<p>Whether a &lt;= b</p>\n</section>\n"
+        ));
+    }
+}
@@ -0,0 +1,255 @@
+/* The canonical formatting of source code.
+
+Line breaks are kept where the author put them, except that blank lines are collapsed to one, each
+top-level form starts on its own line, and closing parens are never left on a line of their own.
+Within a line, elements are separated by one space. Everything else is indentation:
+
+    (defn f (x)           Forms with a body, and any form whose name starts with def or with-,
+      (g x                indent their continuation lines by two. Other calls align their
+         (h x)))          continuation lines with their first argument, or indent by two when
+                          the first argument is not on the same line as the name.
+    (let (a 1
+          b 2)            Lists that don't start with a symbol, and the bindings of let, align
+      (+ a b))            their continuation lines with their first element. */
+
+use front::cst;
+use front::cst::{Cst, Element};
+
+const BODY_FORMS: &[&str] = &["fn", "macro", "let", "if", "begin", "for"];
+
+// Returns the formatted source, or the formatted syntax errors that prevent it from being parsed
+pub fn format_source(filename: &str, input: &str) -> Result<String, String> {
+    let elements = match cst::parse(filename, input) {
+        Ok(elements) => elements,
+        Err(errors) => return Err(::format_syntax_errors_in_source(&errors, input)),
+    };
+
+    let mut p = Printer { out: String::new() };
+    for (i, element) in elements.iter().enumerate() {
+        if i > 0 {
+            let previous_is_line_comment = is_line_comment(&elements[i - 1].cst);
+            if element.newlines_before == 0 && element.cst.is_comment() && !previous_is_line_comment
+            {
+                p.out.push(' ');
+            } else {
+                p.newline(element.newlines_before >= 2, 0);
+            }
+        }
+        p.print(&element.cst, false);
+    }
+
+    if !p.out.is_empty() {
+        p.out.push('\n');
+    }
+    Ok(p.out)
+}
+
+struct Printer {
+    out: String,
+}
+
+impl Printer {
+    fn column(&self) -> usize {
+        let line_start = self.out.rfind('\n').map_or(0, |i| i + 1);
+        self.out[line_start..].chars().count()
+    }
+
+    fn newline(&mut self, blank_line: bool, indent: usize) {
+        let trimmed_len = self.out.trim_end_matches(' ').len();
+        self.out.truncate(trimmed_len);
+        self.out.push('\n');
+        if blank_line {
+            self.out.push('\n');
+        }
+        for _ in 0..indent {
+            self.out.push(' ');
+        }
+    }
+
+    fn print(&mut self, cst: &Cst, is_bindings: bool) {
+        match cst {
+            Cst::Atom(text) | Cst::LineComment(text) | Cst::BlockComment(text) => {
+                self.out.push_str(text)
+            }
+            Cst::Prefixed(prefix, target) => {
+                self.out.push_str(prefix);
+                self.print(target, false);
+            }
+            Cst::List(children) => self.print_list(children, is_bindings, ('(', ')')),
+            // Keys and values, aligned like bindings
+            Cst::Braces(children) => self.print_list(children, true, ('{', '}')),
+        }
+    }
+
+    fn print_list(&mut self, children: &[Element], is_bindings: bool, delimiters: (char, char)) {
+        let open_column = self.column();
+        self.out.push(delimiters.0);
+
+        let head = match children.first() {
+            Some(Element {
+                cst: Cst::Atom(ref text),
+                ..
+            }) if !is_bindings && is_symbol(text) => Some(text.as_ref()),
+            _ => None,
+        };
+        let has_body = match head {
+            Some(name) => {
+                BODY_FORMS.contains(&name) || name.starts_with("def") || name.starts_with("with-")
+            }
+            None => false,
+        };
+        let mut indent = if head.is_some() {
+            open_column + 2
+        } else {
+            open_column + 1
+        };
+
+        for (i, child) in children.iter().enumerate() {
+            let line_breaks = if i == 0 {
+                // Only a comment may start on the line after the opening paren
+                if child.cst.is_comment() {
+                    child.newlines_before
+                } else {
+                    0
+                }
+            } else if is_line_comment(&children[i - 1].cst) {
+                child.newlines_before.max(1)
+            } else {
+                child.newlines_before
+            };
+
+            if line_breaks > 0 {
+                self.newline(line_breaks >= 2 && i > 0, indent);
+            } else if i > 0 {
+                self.out.push(' ');
+                if i == 1 && head.is_some() && !has_body {
+                    indent = self.column();
+                }
+            }
+
+            let child_is_bindings = i == 1 && head == Some("let");
+            self.print(&child.cst, child_is_bindings);
+        }
+
+        if children.last().is_some_and(|c| is_line_comment(&c.cst)) {
+            self.newline(false, indent);
+        }
+        self.out.push(delimiters.1);
+    }
+}
+
+fn is_line_comment(cst: &Cst) -> bool {
+    matches!(cst, Cst::LineComment(..))
+}
+
+fn is_symbol(text: &str) -> bool {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some('"'), _) | (Some('\\'), _) => false,
+        (Some('-'), Some(ch)) => !ch.is_numeric(),
+        (Some(ch), _) => !ch.is_numeric(),
+        (None, _) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use front;
+    use std::fs;
+    use std::path::Path;
+
+    fn fmt(input: &str) -> String {
+        format_source("", input).unwrap()
+    }
+
+    #[test]
+    fn test_indentation() {
+        assert_eq!(
+            fmt("(defn f (x)\n(g x\n(h x)))"),
+            "(defn f (x)\n  (g x\n     (h x)))\n"
+        );
+        assert_eq!(
+            fmt("(cond\n(= a 1) true\n  else   false)"),
+            "(cond\n  (= a 1) true\n  else false)\n"
+        );
+        assert_eq!(
+            fmt("(let (a 1\nb 2)\n(list a\nb))"),
+            "(let (a 1\n      b 2)\n  (list a\n        b))\n"
+        );
+        assert_eq!(fmt("'(1\n2 3)"), "'(1\n  2 3)\n");
+        assert_eq!(
+            fmt("(def ^{:deprecated  true\n:tag number} x 1)"),
+            "(def ^{:deprecated true\n       :tag number} x 1)\n"
+        );
+    }
+
+    #[test]
+    fn test_line_breaks_and_comments() {
+        assert_eq!(
+            fmt("  ; About x\n(def x 1) (def y 2)   \n\n\n\n(f x\n)"),
+            "; About x\n(def x 1)\n(def y 2)\n\n(f x)\n"
+        );
+        assert_eq!(
+            fmt("(f a ; the a\n b ; the b\n)"),
+            "(f a ; the a\n   b ; the b\n   )\n"
+        );
+        assert_eq!(
+            fmt("(def x #| one |# 1) ;; done"),
+            "(def x #| one |# 1) ;; done\n"
+        );
+        assert_eq!(fmt(""), "");
+    }
+
+    #[test]
+    fn test_syntax_errors_are_reported() {
+        assert_eq!(
+            format_source("bad.mn", "(f x"),
+            Err(
+                "Syntax error (bad.mn:1:1): Unbalanced parentheses\n  |\n1 | (f x\n  | ^\n"
+                    .to_string()
+            )
+        );
+    }
+
+    // Formatting must not change what the code means, and formatting twice must change nothing
+    fn check_formatting_of_files_in(dir: &Path) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                check_formatting_of_files_in(&path);
+                continue;
+            }
+            if path.extension().is_none_or(|e| e != "mn") {
+                continue;
+            }
+
+            let input = fs::read_to_string(&path).unwrap();
+            let original = match front::parse("", &input) {
+                Ok(nodes) => nodes,
+                Err(_) => continue,
+            };
+
+            let once = fmt(&input);
+            let twice = fmt(&once);
+            assert_eq!(once, twice, "formatting {:?} is not idempotent", path);
+
+            let formatted = front::parse("", &once).unwrap();
+            let printed = |nodes: Vec<::ast::Node>| -> Vec<String> {
+                nodes.iter().map(|n| format!("{}", n.val)).collect()
+            };
+            assert_eq!(
+                printed(original),
+                printed(formatted),
+                "formatting {:?} changes its meaning",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_formatting_testsuite_and_examples() {
+        check_formatting_of_files_in(Path::new("testsuite"));
+        check_formatting_of_files_in(Path::new("examples"));
+    }
+}
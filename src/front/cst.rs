@@ -0,0 +1,190 @@
+use front;
+use front::scanner::Scanner;
+use front::syntax_error::SyntaxError;
+use front::tokens::Token;
+
+// A concrete syntax tree. Unlike the nodes produced by the parser, it keeps the comments, the
+// original text of each atom, and where the line breaks were.
+#[derive(Debug, PartialEq)]
+pub enum Cst {
+    Atom(String),
+    // A quote or caret, followed by what it applies to
+    Prefixed(String, Box<Cst>),
+    List(Vec<Element>),
+    // The keys and values of metadata, in braces
+    Braces(Vec<Element>),
+    LineComment(String),
+    BlockComment(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Element {
+    pub cst: Cst,
+    // How many line breaks separate the element from whatever precedes it
+    pub newlines_before: usize,
+}
+
+impl Cst {
+    pub fn is_comment(&self) -> bool {
+        matches!(self, Cst::LineComment(..) | Cst::BlockComment(..))
+    }
+}
+
+enum ItemKind {
+    Token(Token),
+    Comment,
+}
+
+struct Item {
+    kind: ItemKind,
+    span: (usize, usize),
+    newlines_before: usize,
+}
+
+// Parses the input into the elements at its top level. Input with syntax errors is rejected
+// with the same errors the parser would report.
+pub fn parse(filename: &str, input: &str) -> Result<Vec<Element>, Vec<SyntaxError>> {
+    front::parse(filename, input)?;
+
+    let items = scan(filename, input)?;
+    let mut elements = Vec::new();
+    let mut i = 0;
+    while i < items.len() {
+        elements.push(parse_element(input, &items, &mut i));
+    }
+    Ok(elements)
+}
+
+fn scan(filename: &str, input: &str) -> Result<Vec<Item>, Vec<SyntaxError>> {
+    let mut s = Scanner::new(filename, input);
+    let mut items = Vec::new();
+    let mut previous_end = 0;
+
+    loop {
+        let result = s.next();
+
+        let mut spans = s.take_comments();
+        let is_end = result == Ok(Token::EndOfFile);
+        if !is_end {
+            spans.push(s.span());
+        }
+
+        let last = spans.len();
+        for (n, span) in spans.into_iter().enumerate() {
+            let kind = if n + 1 == last && !is_end {
+                match result {
+                    Ok(ref token) => ItemKind::Token(token.clone()),
+                    Err(ref e) => return Err(vec![e.clone()]),
+                }
+            } else {
+                ItemKind::Comment
+            };
+            items.push(Item {
+                kind,
+                span,
+                newlines_before: input[previous_end..span.0].matches('\n').count(),
+            });
+            previous_end = span.1;
+        }
+
+        if is_end {
+            return Ok(items);
+        }
+    }
+}
+
+fn parse_element(input: &str, items: &[Item], i: &mut usize) -> Element {
+    let item = &items[*i];
+    *i += 1;
+
+    let text = &input[item.span.0..item.span.1];
+    let cst = match item.kind {
+        ItemKind::Comment if text.starts_with(';') => Cst::LineComment(text.trim_end().to_string()),
+        ItemKind::Comment => Cst::BlockComment(text.to_string()),
+        ItemKind::Token(Token::LeftParen) => {
+            Cst::List(parse_children(input, items, i, Token::RightParen))
+        }
+        ItemKind::Token(Token::LeftBrace) => {
+            Cst::Braces(parse_children(input, items, i, Token::RightBrace))
+        }
+        ItemKind::Token(Token::SingleQuote) | ItemKind::Token(Token::Caret) if *i < items.len() => {
+            let target = parse_element(input, items, i);
+            Cst::Prefixed(text.to_string(), Box::new(target.cst))
+        }
+        ItemKind::Token(..) => Cst::Atom(text.to_string()),
+    };
+
+    Element {
+        cst,
+        newlines_before: item.newlines_before,
+    }
+}
+
+// Parses the elements up to the closing token, which is skipped
+fn parse_children(input: &str, items: &[Item], i: &mut usize, close: Token) -> Vec<Element> {
+    let mut children = Vec::new();
+    while *i < items.len() {
+        match items[*i].kind {
+            ItemKind::Token(ref token) if *token == close => {
+                *i += 1;
+                break;
+            }
+            _ => children.push(parse_element(input, items, i)),
+        }
+    }
+    children
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atom(s: &str, newlines_before: usize) -> Element {
+        Element {
+            cst: Cst::Atom(s.to_string()),
+            newlines_before,
+        }
+    }
+
+    #[test]
+    fn test_comments_and_line_breaks() {
+        let elements = parse("", "; first\n(a 'b ; trailing\n\n  \"c\") #| end |#").unwrap();
+
+        assert_eq!(
+            elements,
+            vec![
+                Element {
+                    cst: Cst::LineComment("; first".to_string()),
+                    newlines_before: 0,
+                },
+                Element {
+                    cst: Cst::List(vec![
+                        atom("a", 0),
+                        Element {
+                            cst: Cst::Prefixed(
+                                "'".to_string(),
+                                Box::new(Cst::Atom("b".to_string()))
+                            ),
+                            newlines_before: 0,
+                        },
+                        Element {
+                            cst: Cst::LineComment("; trailing".to_string()),
+                            newlines_before: 0,
+                        },
+                        atom("\"c\"", 2),
+                    ]),
+                    newlines_before: 1,
+                },
+                Element {
+                    cst: Cst::BlockComment("#| end |#".to_string()),
+                    newlines_before: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        assert!(parse("", "(a b").is_err());
+    }
+}
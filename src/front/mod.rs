@@ -1,10 +1,12 @@
+pub mod cst;
 mod parser;
-mod scanner;
+pub mod scanner;
 pub mod syntax_error;
-mod tokens;
+pub mod tokens;
 
 use ast::*;
 use front::parser::Parser;
+use front::scanner::Scanner;
 use front::syntax_error::SyntaxError;
 use front::tokens::Token;
 
@@ -27,3 +29,89 @@ pub fn parse(filename: &str, input: &str) -> Result<Vec<Node>, Vec<SyntaxError>>
         Ok(values)
     }
 }
+
+// Determines whether the input stops in the middle of a form (such as an unclosed list, string,
+// or multiline comment), so that more input could still complete it
+pub fn is_incomplete(filename: &str, input: &str) -> bool {
+    let mut s = Scanner::new(filename, input);
+    let mut depth = 0;
+    // How many more top-level forms a pending quote or ^ still needs
+    let mut owed = 0;
+
+    loop {
+        match s.next() {
+            Ok(Token::EndOfFile) => return depth > 0 || owed > 0,
+            Ok(Token::LeftParen) | Ok(Token::LeftBrace) => depth += 1,
+            Ok(Token::RightParen) | Ok(Token::RightBrace) => {
+                depth -= 1;
+                if depth < 0 {
+                    // No amount of further input can balance this
+                    return false;
+                }
+                if depth == 0 && owed > 0 {
+                    owed -= 1;
+                }
+            }
+            Err(SyntaxError::UnterminatedStringLiteral(..))
+            | Err(SyntaxError::UnterminatedMultilineComment(..)) => return true,
+            Ok(Token::SingleQuote) if depth == 0 => owed = owed.max(1),
+            // The metadata, then the form it is attached to
+            Ok(Token::Caret) if depth == 0 => owed = owed.max(1) + 1,
+            _ => {
+                if depth == 0 && owed > 0 {
+                    owed -= 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_incomplete() {
+        assert!(is_incomplete("", "(defn f (x)"));
+        assert!(!is_incomplete("", "(defn f (x)\n  x)"));
+        assert!(is_incomplete("", "(println \"abc"));
+        assert!(is_incomplete("", "#| comment"));
+        assert!(is_incomplete("", "'"));
+        assert!(is_incomplete("", "^:private"));
+        assert!(is_incomplete("", "^{:tag number"));
+        assert!(!is_incomplete("", "'^{:tag number} x"));
+        assert!(is_incomplete("", "^:private (def"));
+        assert!(!is_incomplete("", "1 2 3"));
+        assert!(!is_incomplete("", "1)"));
+        assert!(!is_incomplete("", ""));
+    }
+
+    #[test]
+    fn test_metadata() {
+        let nodes = parse("", "^:private ^{:tag number :doc \"x\"} x ^string 'y z").unwrap();
+        let meta = |node: &Node| format!("{}", node.meta.as_ref().unwrap().val);
+
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(meta(&nodes[0]), "(:tag number :doc \"x\" :private true)");
+        assert_eq!(meta(&nodes[1]), "(:tag string)");
+        assert_eq!(format!("{}", nodes[1].val), "(quote y)");
+        assert!(nodes[2].meta.is_none());
+    }
+
+    #[test]
+    fn test_metadata_errors() {
+        let codes = |input: &str| -> Vec<&str> {
+            parse("", input)
+                .unwrap_err()
+                .iter()
+                .map(|e| e.code())
+                .collect()
+        };
+
+        assert_eq!(codes("^{:a} x"), vec!["E0010"]);
+        assert_eq!(codes("^1 x"), vec!["E0010"]);
+        assert_eq!(codes("(def ^(a b) x 1)"), vec!["E0010"]);
+        assert_eq!(codes("^{:a 1"), vec!["E0009"]);
+        assert_eq!(codes("{:a 1}"), vec!["E0004", "E0004"]);
+    }
+}
@@ -16,11 +16,7 @@ impl<'a> Parser<'a> {
         Parser {
             scanner: s,
             current_token: Token::Error,
-            current_loc: Loc::File {
-                filename: "<start>".to_string(),
-                line: 0,
-                pos: 0,
-            },
+            current_loc: Loc::Unknown,
         }
     }
 
@@ -37,6 +33,7 @@ impl<'a> Parser<'a> {
     }
 
     pub fn parse_value(&mut self, errors: &mut Vec<SyntaxError>) -> Node {
+        let start_loc = self.loc();
         let val = match self.current_token {
             Token::Number(ref s) => {
                 match s.parse::<i32>() {
@@ -77,19 +74,33 @@ impl<'a> Parser<'a> {
                 self.next_token(errors);
                 let quoted_value = self.parse_value(errors);
                 let children = vec![
-                    self.make_node(Val::Symbol("quote".to_string())),
+                    Node::new(Val::Symbol("quote".to_string()), start_loc.clone()),
                     quoted_value,
                 ];
                 Val::List(children)
             }
+            Token::Caret => {
+                self.next_token(errors);
+                let entries = self.parse_meta(errors);
+                if self.current_token == Token::EndOfFile {
+                    return Node::new(Val::Error(String::new()), start_loc); // Try to recover by pushing an error Val
+                }
+                let meta_loc = start_loc.to(&self.current_loc);
+
+                // The metadata is attached to the form that follows it, which keeps its own loc
+                self.next_token(errors);
+                let target = self.parse_value(errors);
+                return target.add_meta(&entries, meta_loc);
+            }
             Token::LeftParen => {
                 self.next_token(errors);
                 let mut children = Vec::<Node>::new();
 
                 while self.current_token != Token::RightParen {
                     if self.current_token == Token::EndOfFile {
-                        errors.push(SyntaxError::UnbalancedParens(self.loc()));
-                        return self.make_node(Val::Error(String::new())); // Try to recover by pushing an error Val
+                        // Point at the paren that was never closed, rather than the end of input
+                        errors.push(SyntaxError::UnbalancedParens(start_loc.clone()));
+                        return Node::new(Val::Error(String::new()), start_loc); // Try to recover by pushing an error Val
                     }
 
                     children.push(self.parse_value(errors));
@@ -109,14 +120,62 @@ impl<'a> Parser<'a> {
             }
         };
 
-        self.make_node(val)
+        // Lists and quoted values span from their first token to their last
+        Node::new(val, start_loc.to(&self.current_loc))
     }
 
-    fn loc(&self) -> Loc {
-        self.current_loc.clone()
+    // Parses what follows a ^ into the keys and values of its metadata: {:deprecated true :tag number}
+    // as written, :private meaning {:private true}, and any other symbol, like number, meaning
+    // {:tag number}
+    fn parse_meta(&mut self, errors: &mut Vec<SyntaxError>) -> Vec<Node> {
+        let start_loc = self.loc();
+        match self.current_token.clone() {
+            Token::LeftBrace => {
+                self.next_token(errors);
+                let mut entries = Vec::new();
+
+                while self.current_token != Token::RightBrace {
+                    if self.current_token == Token::EndOfFile {
+                        errors.push(SyntaxError::UnbalancedBraces(start_loc));
+                        return Vec::new();
+                    }
+
+                    entries.push(self.parse_value(errors));
+                    self.next_token(errors);
+                }
+
+                if entries.len() % 2 != 0 {
+                    errors.push(SyntaxError::InvalidMetadata(
+                        start_loc.to(&self.current_loc),
+                    ));
+                    entries.pop(); // Recover by dropping the key without a value
+                }
+                entries
+            }
+            Token::Symbol(ref s) if s.starts_with(':') => vec![
+                Node::new(Val::Symbol(s.clone()), start_loc.clone()),
+                Node::new(Val::Boolean(true), start_loc),
+            ],
+            Token::Symbol(ref s) => vec![
+                Node::new(Val::Symbol(":tag".to_string()), start_loc.clone()),
+                Node::new(Val::Symbol(s.clone()), start_loc),
+            ],
+            Token::LeftParen => {
+                // Recover by skipping the whole list, rather than reading what is in it as forms
+                self.parse_value(errors);
+                errors.push(SyntaxError::InvalidMetadata(
+                    start_loc.to(&self.current_loc),
+                ));
+                Vec::new()
+            }
+            _ => {
+                errors.push(SyntaxError::InvalidMetadata(start_loc));
+                Vec::new()
+            }
+        }
     }
 
-    fn make_node(&self, n: Val) -> Node {
-        Node::new(n, self.current_loc.clone())
+    fn loc(&self) -> Loc {
+        self.current_loc.clone()
     }
 }
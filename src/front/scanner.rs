@@ -10,7 +10,12 @@ pub struct Scanner<'a> {
     input: Peekable<Chars<'a>>,
     filename: &'a str,
     line: i32,
-    pos: i32,
+    column: i32,
+    offset: usize,
+    token_start: usize,
+    token_line: i32,
+    token_column: i32,
+    comments: Vec<(usize, usize)>,
 }
 
 impl<'a> Scanner<'a> {
@@ -19,20 +24,42 @@ impl<'a> Scanner<'a> {
             input: input.chars().peekable(),
             filename: filename,
             line: 1,
-            pos: 0,
+            column: 1,
+            offset: 0,
+            token_start: 0,
+            token_line: 1,
+            token_column: 1,
+            comments: Vec::new(),
         }
     }
 
+    // The span of the most recently scanned token, or of the input scanned so far while scanning
+    // a token
     pub fn loc(&self) -> Loc {
         Loc::File {
             filename: self.filename.to_string(),
-            line: self.line,
-            pos: self.pos,
+            line: self.token_line,
+            column: self.token_column,
+            end_line: self.line,
+            end_column: self.column,
         }
     }
 
+    // Byte offsets of the start and end of the most recently scanned token
+    pub fn span(&self) -> (usize, usize) {
+        (self.token_start, self.offset)
+    }
+
+    // Byte offsets of the comments skipped since this was last called
+    pub fn take_comments(&mut self) -> Vec<(usize, usize)> {
+        self.comments.drain(..).collect()
+    }
+
     pub fn next(&mut self) -> ScanResult {
         self.skip_whitespace();
+        self.token_start = self.offset;
+        self.token_line = self.line;
+        self.token_column = self.column;
 
         match self.read_char() {
             Some(';') => self.scan_single_line_comment(),
@@ -55,6 +82,19 @@ impl<'a> Scanner<'a> {
             }
             Some('(') => Ok(Token::LeftParen),
             Some(')') => Ok(Token::RightParen),
+            Some('{') => Ok(Token::LeftBrace),
+            Some('}') => Ok(Token::RightBrace),
+            // A colon only ever starts a symbol, as in the :flag of metadata
+            Some(':') => {
+                if self.peek_is_symbolic() {
+                    self.scan_symbol(':')
+                } else {
+                    Err(SyntaxError::UnrecognizedCharacterSequence(
+                        ":".to_string(),
+                        self.loc(),
+                    ))
+                }
+            }
             Some('-') => {
                 if let Some(&ch) = self.peek_char() {
                     if ch.is_numeric() {
@@ -97,6 +137,7 @@ impl<'a> Scanner<'a> {
             }
             self.read_char();
         }
+        self.comments.push((self.token_start, self.offset));
         self.next()
     }
 
@@ -104,17 +145,14 @@ impl<'a> Scanner<'a> {
         self.read_char(); // Skip '|'
 
         while let Some(ch) = self.read_char() {
-            match ch {
-                '\n' | '\r' => self.line += 1,
-                '|' => {
-                    if let Some(&chnext) = self.peek_char() {
-                        if chnext == '#' {
-                            self.read_char(); // Consume '#'
-                            return self.next();
-                        }
+            if ch == '|' {
+                if let Some(&chnext) = self.peek_char() {
+                    if chnext == '#' {
+                        self.read_char(); // Consume '#'
+                        self.comments.push((self.token_start, self.offset));
+                        return self.next();
                     }
                 }
-                _ => (),
             }
         }
 
@@ -191,7 +229,24 @@ impl<'a> Scanner<'a> {
     }
 
     fn read_char(&mut self) -> Option<char> {
-        self.input.next()
+        let ch = self.input.next();
+        if let Some(c) = ch {
+            self.offset += c.len_utf8();
+
+            // A carriage return only ends a line when it isn't followed by a line feed
+            let ends_line = match c {
+                '\n' => true,
+                '\r' => self.peek_char() != Some(&'\n'),
+                _ => false,
+            };
+            if ends_line {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+        ch
     }
 
     fn peek_char(&mut self) -> Option<&char> {
@@ -210,19 +265,12 @@ impl<'a> Scanner<'a> {
             if !c.is_whitespace() {
                 break;
             }
-
-            match c {
-                '\n' => self.line += 1,
-                '\r' => self.line += 1,
-                _ => {}
-            }
-
             self.read_char();
         }
     }
 }
 
-fn is_symbolic(ch: char) -> bool {
+pub fn is_symbolic(ch: char) -> bool {
     // NOTE: Don't ever allow these characters: [ ] { } ( ) " , ' ` : ; # | \ ~
     ch.is_alphabetic()
         || ch.is_numeric()
@@ -299,9 +347,12 @@ mod tests {
 
     #[test]
     fn test_miscellaneous() {
-        let mut s = Scanner::new("", r"^ '");
+        let mut s = Scanner::new("", r"^ ' { } :flag");
         assert_eq!(s.next(), Ok(Token::Caret));
         assert_eq!(s.next(), Ok(Token::SingleQuote));
+        assert_eq!(s.next(), Ok(Token::LeftBrace));
+        assert_eq!(s.next(), Ok(Token::RightBrace));
+        assert_eq!(s.next(), Ok(Token::Symbol(":flag".to_string())));
         assert_eq!(s.next(), Ok(Token::EndOfFile));
     }
 
@@ -328,12 +379,66 @@ mod tests {
                 Loc::File {
                     filename: "".to_string(),
                     line: 1,
-                    pos: 0
+                    column: 1,
+                    end_line: 1,
+                    end_column: 2,
                 }
             ))
         );
     }
 
+    #[test]
+    fn test_spans() {
+        let mut s = Scanner::new("", "(ab ; comment\n \"é\")");
+        assert_eq!(s.next(), Ok(Token::LeftParen));
+        assert_eq!(s.span(), (0, 1));
+        assert_eq!(s.next(), Ok(Token::Symbol("ab".to_string())));
+        assert_eq!(s.span(), (1, 3));
+        assert_eq!(s.next(), Ok(Token::StringLiteral("é".to_string())));
+        assert_eq!(s.span(), (15, 19));
+        assert_eq!(s.next(), Ok(Token::RightParen));
+        assert_eq!(s.span(), (19, 20));
+    }
+
+    #[test]
+    fn test_locs() {
+        let mut s = Scanner::new("f", "(ab\r\n  \"x\ny\") #| \n |#é");
+        let mut locs = Vec::new();
+        while s.next() != Ok(Token::EndOfFile) {
+            match s.loc() {
+                Loc::File {
+                    line,
+                    column,
+                    end_line,
+                    end_column,
+                    ..
+                } => locs.push((line, column, end_line, end_column)),
+                Loc::Unknown => unreachable!(),
+            }
+        }
+        assert_eq!(
+            locs,
+            vec![
+                (1, 1, 1, 2),
+                (1, 2, 1, 4),
+                (2, 3, 3, 3),
+                (3, 3, 3, 4),
+                (4, 4, 4, 5)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let mut s = Scanner::new("", "a ; one\n#| two |# b ; three");
+        assert_eq!(s.next(), Ok(Token::Symbol("a".to_string())));
+        assert_eq!(s.take_comments(), vec![]);
+        assert_eq!(s.next(), Ok(Token::Symbol("b".to_string())));
+        assert_eq!(s.take_comments(), vec![(2, 7), (8, 17)]);
+        assert_eq!(s.next(), Ok(Token::EndOfFile));
+        assert_eq!(s.take_comments(), vec![(20, 27)]);
+    }
+
     #[test]
     fn test_quoting() {
         let mut s = Scanner::new("", r"'a");
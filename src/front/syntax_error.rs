@@ -1,7 +1,7 @@
 use front::tokens::Token;
 use loc::Loc;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum SyntaxError {
     UnparsableNumberLiteral(String, Loc),
     UnparsableCharacterLiteral(String, Loc),
@@ -11,6 +11,8 @@ pub enum SyntaxError {
     UnterminatedMultilineComment(Loc),
     UnterminatedStringLiteral(Loc),
     UnbalancedParens(Loc),
+    UnbalancedBraces(Loc),
+    InvalidMetadata(Loc),
 }
 
 impl SyntaxError {
@@ -29,6 +31,10 @@ impl SyntaxError {
             UnterminatedMultilineComment(..) => "Unterminated multiline comment".to_string(),
             UnterminatedStringLiteral(..) => "Unterminated string literal".to_string(),
             UnbalancedParens(..) => "Unbalanced parentheses".to_string(),
+            UnbalancedBraces(..) => "Unbalanced braces".to_string(),
+            InvalidMetadata(..) => {
+                "Metadata must be a :flag, a symbol, or keys and values in braces".to_string()
+            }
         }
     }
 
@@ -43,6 +49,25 @@ impl SyntaxError {
             UnterminatedMultilineComment(l) => l.clone(),
             UnterminatedStringLiteral(l) => l.clone(),
             UnbalancedParens(l) => l.clone(),
+            UnbalancedBraces(l) => l.clone(),
+            InvalidMetadata(l) => l.clone(),
+        }
+    }
+
+    // A code identifying the kind of error, for tools. Codes are never reused or renumbered.
+    pub fn code(&self) -> &'static str {
+        use self::SyntaxError::*;
+        match self {
+            UnparsableNumberLiteral(..) => "E0001",
+            UnparsableCharacterLiteral(..) => "E0002",
+            UnrecognizedCharacterSequence(..) => "E0003",
+            UnrecognizedToken(..) => "E0004",
+            UnrecognizedCharacterInInput(..) => "E0005",
+            UnterminatedMultilineComment(..) => "E0006",
+            UnterminatedStringLiteral(..) => "E0007",
+            UnbalancedParens(..) => "E0008",
+            UnbalancedBraces(..) => "E0009",
+            InvalidMetadata(..) => "E0010",
         }
     }
 }
@@ -4,6 +4,8 @@ pub enum Token {
     EndOfFile,
     LeftParen,
     RightParen,
+    LeftBrace,
+    RightBrace,
     Symbol(String),
     Number(String),
     StringLiteral(String),
@@ -19,6 +21,8 @@ impl Token {
             &Token::EndOfFile => "<eof>".to_string(),
             &Token::LeftParen => "(".to_string(),
             &Token::RightParen => "(".to_string(),
+            &Token::LeftBrace => "{".to_string(),
+            &Token::RightBrace => "}".to_string(),
             &Token::Symbol(ref s) => s.clone(),
             &Token::Number(ref s) => s.clone(),
             &Token::Caret => "^".to_string(),
@@ -0,0 +1,245 @@
+/* Golden-output tests, as in the testsuite directory. Each foo.mn file with a foo.out file beside
+it is a case, which passes when running foo.mn writes what foo.out contains, ignoring trailing
+whitespace. Whatever a case writes is followed by the value of its last form, or by its error. */
+
+use ast::{ReaderObj, WriterObj};
+use back;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub struct CaseResult {
+    pub source: PathBuf,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.expected.trim_end() == self.actual.trim_end()
+    }
+
+    pub fn expected_path(&self) -> PathBuf {
+        self.source.with_extension("out")
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DiffLine {
+    Same(String),
+    Expected(String),
+    Actual(String),
+}
+
+// Cases read the same line whenever they read input, so that they never wait on stdin
+fn reader_function() -> Result<String, String> {
+    Ok("this is a dummy string".to_string())
+}
+
+// The cases under the directory whose paths contain the filter. Source files without an expected
+// output file beside them aren't cases, as they are usually loaded by cases.
+pub fn find_cases(dir: &Path, filter: Option<&str>) -> io::Result<Vec<PathBuf>> {
+    let files = ::collect_source_files(&[dir])?;
+    Ok(files
+        .into_iter()
+        .filter(|file| file.with_extension("out").is_file())
+        .filter(|file| filter.is_none_or(|f| file.to_string_lossy().contains(f)))
+        .collect())
+}
+
+pub fn run_case(source: &Path) -> io::Result<CaseResult> {
+    let input = fs::read_to_string(source)?;
+    let expected = fs::read_to_string(source.with_extension("out"))?;
+
+    let buffer = Rc::new(RefCell::new(Vec::<u8>::new()));
+    let w = WriterObj::Buffer(Rc::clone(&buffer));
+    let env = match back::create_root_env(w, ReaderObj { reader_function }) {
+        Ok(env) => env,
+        Err(e) => return Err(io::Error::other(::format_runtime_error(&e))),
+    };
+    let result = ::parse_eval_print(env, &case_name(source), input.trim_end());
+
+    let written = String::from_utf8_lossy(&buffer.borrow()).into_owned();
+    Ok(CaseResult {
+        source: source.to_path_buf(),
+        expected,
+        actual: format!("{}{}", written, result),
+    })
+}
+
+// The name a case's source is reported by, which is the same however the case was found: the
+// path relative to the current directory, starting with ./, when it is under the current directory
+fn case_name(source: &Path) -> String {
+    let relative = match env::current_dir() {
+        Ok(cwd) if source.is_absolute() => match source.strip_prefix(&cwd) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => return source.to_string_lossy().into_owned(),
+        },
+        _ => source.to_path_buf(),
+    };
+    let normal: PathBuf = relative
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .collect();
+    Path::new(".").join(normal).to_string_lossy().into_owned()
+}
+
+// Runs the cases on as many threads as there are jobs, returning the results in the order of the
+// cases. Environments can't be shared between threads, so each case creates its own.
+pub fn run_cases(sources: Vec<PathBuf>, jobs: usize) -> Vec<io::Result<CaseResult>> {
+    let count = sources.len();
+    let queue = Arc::new(Mutex::new(
+        sources.into_iter().enumerate().collect::<VecDeque<_>>(),
+    ));
+    let (sender, receiver) = mpsc::channel();
+
+    for _ in 0..jobs.max(1).min(count) {
+        let queue = Arc::clone(&queue);
+        let sender = sender.clone();
+        thread::spawn(move || loop {
+            let next = queue.lock().unwrap().pop_front();
+            match next {
+                Some((i, source)) => {
+                    if sender.send((i, run_case(&source))).is_err() {
+                        return;
+                    }
+                }
+                None => return,
+            }
+        });
+    }
+    drop(sender);
+
+    let mut results: Vec<(usize, io::Result<CaseResult>)> = receiver.iter().collect();
+    results.sort_by_key(|&(i, _)| i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+// Makes the actual output the expected output, keeping whether the file ends with a newline
+pub fn update(result: &CaseResult) -> io::Result<()> {
+    let newline = if result.expected.ends_with('\n') {
+        "\n"
+    } else {
+        ""
+    };
+    fs::write(
+        result.expected_path(),
+        format!("{}{}", result.actual.trim_end(), newline),
+    )
+}
+
+// The lines of the expected and actual output, aligned by their longest common subsequence
+pub fn diff_lines(expected: &str, actual: &str) -> Vec<DiffLine> {
+    let expected: Vec<&str> = expected.trim_end().lines().collect();
+    let actual: Vec<&str> = actual.trim_end().lines().collect();
+
+    // lengths[i][j] is the length of the longest common subsequence of expected[i..] and
+    // actual[j..]
+    let mut lengths = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lengths[i][j] = if expected[i] == actual[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() && j < actual.len() {
+        if expected[i] == actual[j] {
+            lines.push(DiffLine::Same(expected[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            lines.push(DiffLine::Expected(expected[i].to_string()));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Actual(actual[j].to_string()));
+            j += 1;
+        }
+    }
+    lines.extend(
+        expected[i..]
+            .iter()
+            .map(|l| DiffLine::Expected(l.to_string())),
+    );
+    lines.extend(actual[j..].iter().map(|l| DiffLine::Actual(l.to_string())));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_lines() {
+        assert_eq!(
+            diff_lines("a\nb\nc\n", "a\nx\nc\nd"),
+            vec![
+                DiffLine::Same("a".to_string()),
+                DiffLine::Expected("b".to_string()),
+                DiffLine::Actual("x".to_string()),
+                DiffLine::Same("c".to_string()),
+                DiffLine::Actual("d".to_string()),
+            ]
+        );
+        assert_eq!(diff_lines("", ""), Vec::new());
+    }
+
+    #[test]
+    fn test_cases_run_in_order_and_update() {
+        let dir = ::std::env::temp_dir().join("macaroon-golden-cases");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.mn"), "(print \"hi\")\n(+ 1 2)").unwrap();
+        fs::write(dir.join("a.out"), "hi3\n").unwrap();
+        fs::write(dir.join("b.mn"), "(list 1 2)").unwrap();
+        fs::write(dir.join("b.out"), "(1 3)\n").unwrap();
+        fs::write(dir.join("helper.mn"), "(def x 1)").unwrap();
+
+        let cases = find_cases(&dir, None).unwrap();
+        assert_eq!(cases, vec![dir.join("a.mn"), dir.join("b.mn")]);
+        assert_eq!(
+            find_cases(&dir, Some("b.")).unwrap(),
+            vec![dir.join("b.mn")]
+        );
+
+        let results: Vec<CaseResult> = run_cases(cases, 4)
+            .into_iter()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(
+            results.iter().map(|r| r.passed()).collect::<Vec<bool>>(),
+            vec![true, false]
+        );
+        assert_eq!(results[1].actual, "(1 2)");
+
+        update(&results[1]).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("b.out")).unwrap(), "(1 2)\n");
+        assert!(run_case(&dir.join("b.mn")).unwrap().passed());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_case_names_do_not_depend_on_how_cases_are_found() {
+        let source = "testsuite/basic/104-unparsable-number.mn";
+        let absolute = env::current_dir().unwrap().join(source);
+
+        for path in &[Path::new(source), &Path::new(".").join(source), &absolute] {
+            assert_eq!(case_name(path), "./testsuite/basic/104-unparsable-number.mn");
+        }
+        let results = run_cases(find_cases(Path::new("testsuite/basic"), None).unwrap(), 4);
+        assert!(results.into_iter().all(|r| r.unwrap().passed()));
+    }
+}
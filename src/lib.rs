@@ -1,45 +1,237 @@
+// Runtime errors are returned by value throughout the interpreter. They are large because
+// they carry the values involved, but they are only built on the way out of an evaluation.
+#![allow(clippy::result_large_err)]
+
+#[macro_use]
+extern crate serde_json;
+
 pub mod ast;
 pub mod back;
+pub mod check;
+pub mod coverage;
+pub mod debugger;
+pub mod doc;
+pub mod fmt;
+pub mod golden;
 mod front;
-mod loc;
+pub mod loc;
+pub mod lsp;
+pub mod profile;
+pub mod repl;
+pub mod report;
+pub mod server;
+pub mod testing;
+
+pub use front::syntax_error::SyntaxError;
 
+use ast::Node;
 use back::env::SmartEnv;
+use back::runtime_error::RuntimeError;
 use loc::Loc;
+use report::ErrorReport;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 pub fn parse_eval_print(env: SmartEnv, filename: &str, input: &str) -> String {
     let parse_result = front::parse(filename, input);
 
     match parse_result {
         Ok(nodes) => {
-            let eval_result = back::eval(env, nodes);
+            let eval_result = back::eval(Rc::clone(&env), nodes);
             match eval_result {
                 Ok(output_node) => format!("{}", output_node.val),
-                Err(runtime_error) => match runtime_error.loc() {
-                    Loc::File { filename, line, .. } => format!(
-                        "Runtime error ({}:{}): {}\n",
-                        filename,
-                        line,
-                        runtime_error.display()
-                    ),
-                    Loc::Unknown => format!("Runtime error: {}\n", runtime_error.display()),
-                },
+                Err(runtime_error) => {
+                    // The error is handled here, so its stack must not be mistaken for that of
+                    // a later error in an enclosing evaluation, such as the one that called load
+                    back::take_error_stack(&env);
+                    format_runtime_error_in_source(&runtime_error, filename, input)
+                }
             }
         }
+        Err(syntax_errors) => format_syntax_errors_in_source(&syntax_errors, input),
+    }
+}
+
+// Like parse_eval_print, but for tools: returns the value of the last form, or reports of the
+// errors that stopped the evaluation
+pub fn parse_eval(env: SmartEnv, filename: &str, input: &str) -> Result<Node, Vec<ErrorReport>> {
+    let nodes = match front::parse(filename, input) {
+        Ok(nodes) => nodes,
         Err(syntax_errors) => {
-            let mut output = String::new();
-            for syntax_error in syntax_errors {
-                let s = match syntax_error.loc() {
-                    Loc::File { filename, line, .. } => format!(
-                        "Syntax error ({}:{}): {}\n",
-                        filename,
-                        line,
-                        syntax_error.display()
-                    ),
-                    Loc::Unknown => format!("Syntax error: {}\n", syntax_error.display(),),
-                };
-                output.push_str(&s);
+            return Err(syntax_errors
+                .iter()
+                .map(ErrorReport::from_syntax_error)
+                .collect())
+        }
+    };
+
+    back::eval(Rc::clone(&env), nodes).map_err(|runtime_error| {
+        let stack = back::take_error_stack(&env);
+        vec![ErrorReport::from_runtime_error(&runtime_error, stack)]
+    })
+}
+
+// Like load_file, but for tools, in the manner of parse_eval
+pub fn eval_file(env: SmartEnv, path: &str) -> Result<Node, Vec<ErrorReport>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => parse_eval(env, path, &contents),
+        Err(e) => Err(vec![ErrorReport::from_runtime_error(
+            &load_failure(path, e),
+            Vec::new(),
+        )]),
+    }
+}
+
+// Loads a file into the environment, returning the printed result of its last form or its errors
+pub fn load_file(env: SmartEnv, path: &str) -> String {
+    match fs::read_to_string(path) {
+        Ok(contents) => parse_eval_print(env, path, &contents),
+        Err(e) => format_runtime_error(&load_failure(path, e)),
+    }
+}
+
+fn load_failure(path: &str, e: io::Error) -> RuntimeError {
+    RuntimeError::IoFailure {
+        operation: "load file".to_string(),
+        path: path.to_string(),
+        message: e.to_string(),
+        loc: Loc::Unknown,
+    }
+}
+
+// Expands the paths into the source files they name, searching directories recursively for *.mn
+// files. The files found in each directory are sorted.
+pub fn collect_source_files<P: AsRef<Path>>(paths: &[P]) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        let path = path.as_ref();
+        if path.is_dir() {
+            let mut entries = Vec::new();
+            for entry in fs::read_dir(path)? {
+                let entry_path = entry?.path();
+                if entry_path.is_dir() || entry_path.extension().is_some_and(|e| e == "mn") {
+                    entries.push(entry_path);
+                }
             }
-            output
+            entries.sort();
+            files.append(&mut collect_source_files(&entries)?);
+        } else {
+            files.push(path.to_path_buf());
         }
     }
+    Ok(files)
+}
+
+// Whether the input ends in the middle of a form, so that a REPL should read more lines
+pub fn is_input_incomplete(input: &str) -> bool {
+    front::is_incomplete("REPL", input)
+}
+
+pub fn format_runtime_error(runtime_error: &RuntimeError) -> String {
+    match runtime_error.loc() {
+        Loc::File {
+            filename,
+            line,
+            column,
+            ..
+        } => format!(
+            "Runtime error ({}:{}:{}): {}\n",
+            filename,
+            line,
+            column,
+            runtime_error.display()
+        ),
+        Loc::Unknown => format!("Runtime error: {}\n", runtime_error.display()),
+    }
+}
+
+// Like format_runtime_error, followed by the line of source code where the error happened. The
+// source is the input when the error is in it, or else is read from the file the error is in.
+pub fn format_runtime_error_in_source(
+    runtime_error: &RuntimeError,
+    filename: &str,
+    input: &str,
+) -> String {
+    let loc = runtime_error.loc();
+    let snippet = match loc {
+        Loc::File {
+            filename: ref error_filename,
+            ..
+        } if error_filename != filename => match fs::read_to_string(error_filename) {
+            Ok(source) => loc.snippet(&source),
+            Err(_) => None,
+        },
+        _ => loc.snippet(input),
+    };
+    match snippet {
+        // Messages such as those of panics can end with a newline of their own
+        Some(snippet) => format!(
+            "{}\n{}",
+            format_runtime_error(runtime_error).trim_end_matches('\n'),
+            snippet
+        ),
+        None => format_runtime_error(runtime_error),
+    }
+}
+
+fn format_syntax_errors(syntax_errors: &[SyntaxError]) -> String {
+    let mut output = String::new();
+    for syntax_error in syntax_errors {
+        output.push_str(&format_syntax_error(syntax_error));
+    }
+    output
+}
+
+fn format_syntax_error(syntax_error: &SyntaxError) -> String {
+    match syntax_error.loc() {
+        Loc::File {
+            filename,
+            line,
+            column,
+            ..
+        } => format!(
+            "Syntax error ({}:{}:{}): {}\n",
+            filename,
+            line,
+            column,
+            syntax_error.display()
+        ),
+        Loc::Unknown => format!("Syntax error: {}\n", syntax_error.display()),
+    }
+}
+
+// Like format_syntax_errors, with each error followed by the line of the input where it is
+fn format_syntax_errors_in_source(syntax_errors: &[SyntaxError], input: &str) -> String {
+    let mut output = String::new();
+    for syntax_error in syntax_errors {
+        output.push_str(&format_syntax_error(syntax_error));
+        output.push_str(&syntax_error.loc().snippet(input).unwrap_or_default());
+    }
+    output
+}
+
+// Root environments for unit tests, whose reads of input always get an empty line
+
+#[cfg(test)]
+fn test_reader() -> ast::ReaderObj {
+    fn reader_function() -> Result<String, String> {
+        Ok(String::new())
+    }
+    ast::ReaderObj { reader_function }
+}
+
+#[cfg(test)]
+fn test_env() -> SmartEnv {
+    back::create_root_env(ast::WriterObj::Sink, test_reader()).unwrap()
+}
+
+// A root environment with the reader, whose *writer* writes to the returned buffer
+#[cfg(test)]
+fn test_env_with_buffer(reader: ast::ReaderObj) -> (SmartEnv, Rc<std::cell::RefCell<Vec<u8>>>) {
+    let buffer = Rc::new(std::cell::RefCell::new(Vec::new()));
+    let writer = ast::WriterObj::Buffer(Rc::clone(&buffer));
+    let env = back::create_root_env(writer, reader).unwrap();
+    (env, buffer)
 }
@@ -1,9 +1,129 @@
+// A span of source code. Lines and columns start at 1, columns count characters, and the end is
+// just past the last character of the span.
 #[derive(Debug, PartialEq, Clone)]
 pub enum Loc {
     File {
         filename: String,
         line: i32,
-        pos: i32,
+        column: i32,
+        end_line: i32,
+        end_column: i32,
     },
     Unknown,
 }
+
+impl Loc {
+    // The span from the start of this location to the end of the other one
+    pub fn to(&self, end: &Loc) -> Loc {
+        match (self, end) {
+            (
+                Loc::File {
+                    filename,
+                    line,
+                    column,
+                    ..
+                },
+                Loc::File {
+                    end_line,
+                    end_column,
+                    ..
+                },
+            ) => Loc::File {
+                filename: filename.clone(),
+                line: *line,
+                column: *column,
+                end_line: *end_line,
+                end_column: *end_column,
+            },
+            (Loc::File { .. }, Loc::Unknown) => self.clone(),
+            (Loc::Unknown, _) => end.clone(),
+        }
+    }
+
+    // The source line of the start of the span, with the span underlined by carets. Spans over
+    // several lines are underlined to the end of their first line.
+    pub fn snippet(&self, source: &str) -> Option<String> {
+        let (line, column, end_line, end_column) = match *self {
+            Loc::File {
+                line,
+                column,
+                end_line,
+                end_column,
+                ..
+            } => (line, column, end_line, end_column),
+            Loc::Unknown => return None,
+        };
+        if line < 1 {
+            return None;
+        }
+        let text = source
+            .lines()
+            .nth((line - 1) as usize)?
+            .trim_end_matches('\r');
+
+        let start = (column.max(1) - 1) as usize;
+        let line_len = text.chars().count();
+        let end = if end_line == line {
+            (end_column.max(1) - 1) as usize
+        } else {
+            line_len
+        };
+
+        // Tabs are kept so that the carets line up with the text however tabs are displayed
+        let mut underline: String = text
+            .chars()
+            .take(start)
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+        for _ in start..end.max(start + 1) {
+            underline.push('^');
+        }
+
+        let number = line.to_string();
+        let gutter = " ".repeat(number.len());
+        Some(format!(
+            "{} |\n{} | {}\n{} | {}\n",
+            gutter, number, text, gutter, underline
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loc(line: i32, column: i32, end_line: i32, end_column: i32) -> Loc {
+        Loc::File {
+            filename: "".to_string(),
+            line,
+            column,
+            end_line,
+            end_column,
+        }
+    }
+
+    #[test]
+    fn test_to() {
+        assert_eq!(loc(1, 2, 1, 3).to(&loc(4, 5, 4, 6)), loc(1, 2, 4, 6));
+        assert_eq!(loc(1, 2, 1, 3).to(&Loc::Unknown), loc(1, 2, 1, 3));
+    }
+
+    #[test]
+    fn test_snippet() {
+        let source = "(def x 1)\n\t(foo x\n  y)";
+        assert_eq!(
+            loc(1, 6, 1, 7).snippet(source),
+            Some("  |\n1 | (def x 1)\n  |      ^\n".to_string())
+        );
+        assert_eq!(
+            loc(2, 2, 3, 5).snippet(source),
+            Some("  |\n2 | \t(foo x\n  | \t^^^^^^\n".to_string())
+        );
+        assert_eq!(
+            loc(3, 5, 3, 5).snippet(source),
+            Some("  |\n3 |   y)\n  |     ^\n".to_string())
+        );
+        assert_eq!(loc(9, 1, 9, 2).snippet(source), None);
+        assert_eq!(Loc::Unknown.snippet(source), None);
+    }
+}
@@ -0,0 +1,839 @@
+/* A language server, speaking the Language Server Protocol over a pair of streams.
+
+Open documents are only ever scanned and parsed, never evaluated. Names defined in the documents
+with def, defn and defmacro are found from their tokens, so that their exact positions are known.
+Everything else (the primitives and special forms) is described from a root environment. */
+
+use ast::{ReaderObj, WriterObj};
+use back;
+use back::env::SmartEnv;
+use front;
+use front::scanner::{is_symbolic, Scanner};
+use front::tokens::Token;
+use loc::Loc;
+use repl;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, Write};
+
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+const SEVERITY_ERROR: u64 = 1;
+
+const SYMBOL_KIND_FUNCTION: u64 = 12;
+const SYMBOL_KIND_VARIABLE: u64 = 13;
+
+const COMPLETION_KIND_FUNCTION: u64 = 3;
+const COMPLETION_KIND_VARIABLE: u64 = 6;
+const COMPLETION_KIND_KEYWORD: u64 = 14;
+
+const DEFINING_FORMS: &[&str] = &["def", "defn", "defmacro"];
+
+// A name defined in a document. Spans are byte offsets into the document's text.
+#[derive(Debug, PartialEq)]
+struct Definition {
+    name: String,
+    is_routine: bool,
+    signature: String,
+    doc: Option<String>,
+    name_span: (usize, usize),
+    form_span: (usize, usize),
+}
+
+struct ScannedToken {
+    token: Token,
+    span: (usize, usize),
+}
+
+struct Server {
+    documents: HashMap<String, String>,
+    env: SmartEnv,
+    shutdown_requested: bool,
+}
+
+fn reader_function() -> Result<String, String> {
+    Err("Input cannot be read by the language server".to_string())
+}
+
+// Serves requests read from the input until the client sends exit or closes the input
+pub fn run<R: BufRead, W: Write>(mut input: R, mut output: W) -> io::Result<()> {
+    let env = match back::create_root_env(WriterObj::Sink, ReaderObj { reader_function }) {
+        Ok(env) => env,
+        Err(e) => return Err(io::Error::other(e.display())),
+    };
+    let mut server = Server {
+        documents: HashMap::new(),
+        env,
+        shutdown_requested: false,
+    };
+
+    while let Some(message) = read_message(&mut input)? {
+        let method = message.get("method").and_then(Value::as_str).unwrap_or("");
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+        if method == "exit" {
+            break;
+        }
+
+        match message.get("id") {
+            // Requests are answered with either a result or an error
+            Some(id) => {
+                let mut response = Map::new();
+                response.insert("jsonrpc".to_string(), json_string("2.0"));
+                response.insert("id".to_string(), id.clone());
+                match server.handle_request(method, &params) {
+                    Ok(result) => {
+                        response.insert("result".to_string(), result);
+                    }
+                    Err((code, error_message)) => {
+                        let mut error = Map::new();
+                        error.insert("code".to_string(), Value::from(code));
+                        error.insert("message".to_string(), json_string(&error_message));
+                        response.insert("error".to_string(), Value::Object(error));
+                    }
+                }
+                write_message(&mut output, &Value::Object(response))?;
+            }
+            // Notifications are never answered, but may cause diagnostics to be published
+            None => {
+                for notification in server.handle_notification(method, &params) {
+                    write_message(&mut output, &notification)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Reads one message framed by a Content-Length header, or returns None at the end of the input
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Map<String, Value>>> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            if content_length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some(value) = header.split_once(':').map(|(_, value)| value) {
+            if header.to_lowercase().starts_with("content-length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length.unwrap()];
+    input.read_exact(&mut body)?;
+    match serde_json::from_slice(&body) {
+        Ok(Value::Object(message)) => Ok(Some(message)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Messages must be JSON objects",
+        )),
+    }
+}
+
+fn write_message<W: Write>(output: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+impl Server {
+    fn handle_request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        if self.shutdown_requested {
+            return Err((INVALID_REQUEST, "The server has been shut down".to_string()));
+        }
+
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "documentSymbolProvider": true,
+                    "completionProvider": {}
+                },
+                "serverInfo": { "name": "macaroon-lsp" }
+            })),
+            "shutdown" => {
+                self.shutdown_requested = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => {
+                let (uri, offset) = self.position_params(params)?;
+                Ok(self.definition(&uri, offset))
+            }
+            "textDocument/hover" => {
+                let (uri, offset) = self.position_params(params)?;
+                Ok(self.hover(&uri, offset))
+            }
+            "textDocument/documentSymbol" => {
+                let uri = document_uri(params)?;
+                Ok(self.document_symbols(&uri))
+            }
+            "textDocument/completion" => {
+                let (uri, offset) = self.position_params(params)?;
+                Ok(self.completion(&uri, offset))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method: {}", method))),
+        }
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = match document_uri(params) {
+            Ok(uri) => uri,
+            Err(_) => return Vec::new(),
+        };
+
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.documents.insert(uri.clone(), text.to_string());
+            }
+            // A change to a document that was never opened is ignored
+            "textDocument/didChange" if !self.documents.contains_key(&uri) => return Vec::new(),
+            "textDocument/didChange" => {
+                // Only full synchronization is offered, so the last change is the whole text
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes
+                    .and_then(|c| c.last())
+                    .and_then(|c| c["text"].as_str())
+                {
+                    self.documents.insert(uri.clone(), text.to_string());
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                // Clear the diagnostics of the closed document
+                return vec![publish_diagnostics(&uri, Vec::new())];
+            }
+            _ => return Vec::new(),
+        }
+
+        let diagnostics = match self.documents.get(&uri) {
+            Some(text) => diagnostics(&uri, text),
+            None => Vec::new(),
+        };
+        vec![publish_diagnostics(&uri, diagnostics)]
+    }
+
+    fn position_params(&self, params: &Value) -> Result<(String, usize), (i64, String)> {
+        let uri = document_uri(params)?;
+        let text = match self.documents.get(&uri) {
+            Some(text) => text,
+            None => return Err((INVALID_PARAMS, format!("Unknown document: {}", uri))),
+        };
+        let line = params["position"]["line"].as_u64().unwrap_or(0) as usize;
+        let character = params["position"]["character"].as_u64().unwrap_or(0) as usize;
+        Ok((uri, position_to_offset(text, line, character)))
+    }
+
+    fn definition(&self, uri: &str, offset: usize) -> Value {
+        let name = match self.documents.get(uri).and_then(|text| symbol_at(text, offset)) {
+            Some((name, _)) => name,
+            None => return Value::Null,
+        };
+
+        // Definitions in the same document are preferred over those in other open documents
+        let mut uris: Vec<&String> = self.documents.keys().filter(|u| *u != uri).collect();
+        uris.sort();
+        let current = uri.to_string();
+        uris.insert(0, &current);
+
+        for u in uris {
+            let text = match self.documents.get(u) {
+                Some(text) => text,
+                None => continue,
+            };
+            if let Some(d) = definitions(text).into_iter().find(|d| d.name == name) {
+                return json!({ "uri": u, "range": range(text, d.name_span) });
+            }
+        }
+        Value::Null
+    }
+
+    fn hover(&self, uri: &str, offset: usize) -> Value {
+        let text = match self.documents.get(uri) {
+            Some(text) => text,
+            None => return Value::Null,
+        };
+        let (name, span) = match symbol_at(text, offset) {
+            Some(symbol) => symbol,
+            None => return Value::Null,
+        };
+
+        let contents = match definitions(text).into_iter().find(|d| d.name == name) {
+            Some(d) => {
+                let mut contents = format!("```macaroon\n{}\n```", d.signature);
+                if let Some(doc) = d.doc {
+                    contents.push_str("\n\n");
+                    contents.push_str(&doc);
+                }
+                contents
+            }
+            None => {
+                let description = repl::describe(&self.env, &name);
+                if description.starts_with("Undefined name") {
+                    return Value::Null;
+                }
+                match repl::documentation(&self.env, &name) {
+                    Some(doc) => format!("```macaroon\n{}\n```\n\n{}", description, doc),
+                    None => format!("```macaroon\n{}\n```", description),
+                }
+            }
+        };
+
+        json!({
+            "contents": { "kind": "markdown", "value": contents },
+            "range": range(text, span)
+        })
+    }
+
+    fn document_symbols(&self, uri: &str) -> Value {
+        let text = match self.documents.get(uri) {
+            Some(text) => text,
+            None => return Value::Array(Vec::new()),
+        };
+
+        let symbols = definitions(text)
+            .into_iter()
+            .map(|d| {
+                let kind = if d.is_routine {
+                    SYMBOL_KIND_FUNCTION
+                } else {
+                    SYMBOL_KIND_VARIABLE
+                };
+                json!({
+                    "name": d.name,
+                    "detail": d.signature,
+                    "kind": kind,
+                    "range": range(text, d.form_span),
+                    "selectionRange": range(text, d.name_span)
+                })
+            })
+            .collect();
+        Value::Array(symbols)
+    }
+
+    fn completion(&self, uri: &str, offset: usize) -> Value {
+        let text = match self.documents.get(uri) {
+            Some(text) => text,
+            None => return Value::Null,
+        };
+        let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let (start, candidates) =
+            repl::complete(&self.env, &text[line_start..offset], offset - line_start);
+        let prefix = &text[line_start + start..offset];
+
+        let mut items: Vec<Value> = Vec::new();
+        let mut seen: Vec<String> = Vec::new();
+        for d in definitions(text) {
+            if d.name.starts_with(prefix) && !seen.contains(&d.name) {
+                let kind = if d.is_routine {
+                    COMPLETION_KIND_FUNCTION
+                } else {
+                    COMPLETION_KIND_VARIABLE
+                };
+                items.push(json!({ "label": d.name, "kind": kind, "detail": d.signature }));
+                seen.push(d.name);
+            }
+        }
+        for candidate in candidates {
+            if seen.contains(&candidate) {
+                continue;
+            }
+            let detail = repl::describe(&self.env, &candidate);
+            let kind = if detail.ends_with("special form") {
+                COMPLETION_KIND_KEYWORD
+            } else if detail.contains("primitive")
+                || detail.ends_with("function")
+                || detail.ends_with("macro")
+            {
+                COMPLETION_KIND_FUNCTION
+            } else {
+                COMPLETION_KIND_VARIABLE
+            };
+            items.push(json!({ "label": candidate, "kind": kind, "detail": detail }));
+        }
+        Value::Array(items)
+    }
+}
+
+fn json_string(s: &str) -> Value {
+    Value::String(s.to_string())
+}
+
+fn document_uri(params: &Value) -> Result<String, (i64, String)> {
+    match params["textDocument"]["uri"].as_str() {
+        Some(uri) => Ok(uri.to_string()),
+        None => Err((INVALID_PARAMS, "Missing textDocument.uri".to_string())),
+    }
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics }
+    })
+}
+
+fn diagnostics(uri: &str, text: &str) -> Vec<Value> {
+    let errors = match front::parse(uri, text) {
+        Ok(_) => return Vec::new(),
+        Err(errors) => errors,
+    };
+
+    errors
+        .iter()
+        .map(|e| {
+            let (start, end) = match e.loc() {
+                Loc::File {
+                    line,
+                    column,
+                    end_line,
+                    end_column,
+                    ..
+                } => (
+                    loc_to_position(text, line, column),
+                    loc_to_position(text, end_line, end_column),
+                ),
+                Loc::Unknown => (loc_to_position(text, 1, 1), loc_to_position(text, 1, 1)),
+            };
+            json!({
+                "range": { "start": start, "end": end },
+                "severity": SEVERITY_ERROR,
+                "source": "macaroon",
+                "message": e.display()
+            })
+        })
+        .collect()
+}
+
+fn scan(text: &str) -> Vec<ScannedToken> {
+    let mut s = Scanner::new("", text);
+    let mut tokens = Vec::new();
+    loop {
+        let token = match s.next() {
+            Ok(Token::EndOfFile) => break,
+            Ok(token) => token,
+            Err(_) => Token::Error,
+        };
+        tokens.push(ScannedToken {
+            token,
+            span: s.span(),
+        });
+    }
+    tokens
+}
+
+// Finds the index of the right paren closing the left paren at the given index
+fn closing_paren(tokens: &[ScannedToken], open: usize) -> Option<usize> {
+    closing(tokens, open, &Token::LeftParen, &Token::RightParen)
+}
+
+fn closing(tokens: &[ScannedToken], open: usize, left: &Token, right: &Token) -> Option<usize> {
+    let mut depth = 0;
+    for (i, t) in tokens.iter().enumerate().skip(open) {
+        if t.token == *left {
+            depth += 1;
+        } else if t.token == *right {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+// Skips the metadata before the form at the given index, as in (def ^:private x 1), returning
+// the index of the form itself. Metadata that is a list is skipped whole, as the parser does.
+fn skip_metadata(tokens: &[ScannedToken], mut i: usize) -> usize {
+    while tokens.get(i).map(|t| &t.token) == Some(&Token::Caret) {
+        let end = match tokens.get(i + 1).map(|t| &t.token) {
+            Some(Token::LeftBrace) => closing(tokens, i + 1, &Token::LeftBrace, &Token::RightBrace),
+            Some(Token::LeftParen) => closing_paren(tokens, i + 1),
+            _ => Some(i + 1),
+        };
+        i = end.map_or(tokens.len(), |end| end + 1);
+    }
+    i
+}
+
+// Finds every (def name ...), (defn name (params) ...) and (defmacro name (params) ...) form
+fn definitions(text: &str) -> Vec<Definition> {
+    let tokens = scan(text);
+    let mut found = Vec::new();
+
+    for i in 0..tokens.len() {
+        if tokens[i].token != Token::LeftParen {
+            continue;
+        }
+        let form = match tokens.get(i + 1).map(|t| &t.token) {
+            Some(Token::Symbol(form)) if DEFINING_FORMS.contains(&form.as_ref()) => form.clone(),
+            _ => continue,
+        };
+        let name_index = skip_metadata(&tokens, i + 2);
+        let (name, name_span) = match tokens.get(name_index) {
+            Some(ScannedToken {
+                token: Token::Symbol(name),
+                span,
+            }) => (name.clone(), *span),
+            _ => continue,
+        };
+        let close = closing_paren(&tokens, i);
+        let form_end = close.map_or(text.len(), |c| tokens[c].span.1);
+
+        // For defn and defmacro, the params follow the name. For def, they follow fn or macro.
+        let params_index = if form == "def" {
+            match (tokens.get(name_index + 1), tokens.get(name_index + 2)) {
+                (
+                    Some(ScannedToken {
+                        token: Token::LeftParen,
+                        ..
+                    }),
+                    Some(ScannedToken {
+                        token: Token::Symbol(ref head),
+                        ..
+                    }),
+                ) if head == "fn" || head == "macro" => Some(name_index + 3),
+                _ => None,
+            }
+        } else {
+            Some(name_index + 1)
+        };
+
+        let params = params_index
+            .filter(|&p| tokens.get(p).map(|t| &t.token) == Some(&Token::LeftParen))
+            .and_then(|p| closing_paren(&tokens, p).map(|c| (p, c)));
+
+        let (signature, doc) = match params {
+            Some((open, close_params)) => {
+                let params_text = &text[tokens[open].span.0..tokens[close_params].span.1];
+                // A string directly after the params, which is not the whole body, documents it
+                let doc = match (tokens.get(close_params + 1), tokens.get(close_params + 2)) {
+                    (
+                        Some(ScannedToken {
+                            token: Token::StringLiteral(ref doc),
+                            ..
+                        }),
+                        Some(next),
+                    ) if next.token != Token::RightParen => Some(doc.clone()),
+                    _ => None,
+                };
+                (format!("({} {} {})", form, name, params_text), doc)
+            }
+            None => (format!("({} {})", form, name), None),
+        };
+
+        found.push(Definition {
+            name,
+            is_routine: params.is_some(),
+            signature,
+            doc,
+            name_span,
+            form_span: (tokens[i].span.0, form_end),
+        });
+    }
+    found
+}
+
+// The symbol containing the offset, or ending right at it
+fn symbol_at(text: &str, offset: usize) -> Option<(String, (usize, usize))> {
+    let start = text[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, ch)| is_symbolic(ch))
+        .last()
+        .map_or(offset, |(i, _)| i);
+    let end = text[offset..]
+        .char_indices()
+        .find(|&(_, ch)| !is_symbolic(ch))
+        .map_or(text.len(), |(i, _)| offset + i);
+
+    if start == end {
+        None
+    } else {
+        Some((text[start..end].to_string(), (start, end)))
+    }
+}
+
+fn utf16_len(s: &str) -> usize {
+    s.chars().map(char::len_utf16).sum()
+}
+
+// LSP positions count lines from zero, and characters in UTF-16 code units
+fn offset_to_position(text: &str, offset: usize) -> Value {
+    let before = &text[..offset];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    json!({ "line": line, "character": utf16_len(&before[line_start..]) })
+}
+
+// Converts a line and column of a Loc, which count from one and count characters
+fn loc_to_position(text: &str, line: i32, column: i32) -> Value {
+    let line = (line.max(1) - 1) as usize;
+    let line_text = text.split('\n').nth(line).unwrap_or("");
+    let prefix: String = line_text
+        .chars()
+        .take((column.max(1) - 1) as usize)
+        .collect();
+    json!({ "line": line, "character": utf16_len(&prefix) })
+}
+
+fn position_to_offset(text: &str, line: usize, character: usize) -> usize {
+    let mut line_start = 0;
+    for _ in 0..line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+
+    let mut units = 0;
+    for (i, ch) in text[line_start..].char_indices() {
+        if units >= character || ch == '\n' {
+            return line_start + i;
+        }
+        units += ch.len_utf16();
+    }
+    text.len()
+}
+
+fn range(text: &str, span: (usize, usize)) -> Value {
+    json!({
+        "start": offset_to_position(text, span.0),
+        "end": offset_to_position(text, span.1)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const URI: &str = "file:///work/example.mn";
+
+    // Frames each message as a client would, then runs the server over all of them
+    fn run_script(messages: &[Value]) -> Vec<Value> {
+        let mut input = Vec::new();
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+        let mut output = Vec::new();
+        run(Cursor::new(input), &mut output).unwrap();
+
+        let mut replies = Vec::new();
+        let mut reader = Cursor::new(output);
+        while let Some(reply) = read_message(&mut reader).unwrap() {
+            replies.push(Value::Object(reply));
+        }
+        replies
+    }
+
+    fn open(text: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": { "uri": URI, "languageId": "macaroon", "version": 1, "text": text }
+            }
+        })
+    }
+
+    fn request(id: u64, method: &str, line: u64, character: u64) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": {
+                "textDocument": { "uri": URI },
+                "position": { "line": line, "character": character }
+            }
+        })
+    }
+
+    const SOURCE: &str = "(defn square (x)\n  \"Multiplies x by itself\"\n  (* x x))\n\n(def answer 42)\n(square answer)\n";
+
+    #[test]
+    fn test_lifecycle_and_diagnostics() {
+        let replies = run_script(&[
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+            json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+            open("(def x 1)\n(def y (+ x 1)\n"),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "shutdown" }),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "shutdown" }),
+        ]);
+
+        assert_eq!(replies.len(), 3);
+        assert_eq!(replies[0]["id"], 1);
+        assert_eq!(replies[0]["result"]["capabilities"]["hoverProvider"], true);
+
+        let diagnostics = &replies[1]["params"]["diagnostics"];
+        assert_eq!(replies[1]["method"], "textDocument/publishDiagnostics");
+        assert_eq!(diagnostics[0]["message"], "Unbalanced parentheses");
+        assert_eq!(
+            diagnostics[0]["range"],
+            json!({
+                "start": { "line": 1, "character": 0 },
+                "end": { "line": 1, "character": 1 }
+            })
+        );
+
+        assert_eq!(
+            replies[2],
+            json!({ "jsonrpc": "2.0", "id": 2, "result": null })
+        );
+    }
+
+    #[test]
+    fn test_change_before_open() {
+        let replies = run_script(&[
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didChange",
+                "params": {
+                    "textDocument": { "uri": URI, "version": 2 },
+                    "contentChanges": [{ "range": null }]
+                }
+            }),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didChange",
+                "params": {
+                    "textDocument": { "uri": URI, "version": 3 },
+                    "contentChanges": [{ "text": "(def x" }]
+                }
+            }),
+            request(1, "textDocument/hover", 0, 1),
+            open("(def x 1)\n"),
+            request(2, "textDocument/hover", 0, 1),
+        ]);
+
+        assert_eq!(replies.len(), 3);
+        assert_eq!(replies[0]["error"]["message"], format!("Unknown document: {}", URI));
+        assert_eq!(replies[1]["params"]["diagnostics"], json!([]));
+        assert_eq!(
+            replies[2]["result"]["contents"]["value"],
+            "```macaroon\ndef: special form\n```"
+        );
+    }
+
+    #[test]
+    fn test_definition_and_hover() {
+        let replies = run_script(&[
+            open(SOURCE),
+            request(1, "textDocument/definition", 5, 3),
+            request(2, "textDocument/hover", 5, 3),
+            request(3, "textDocument/hover", 5, 0),
+            request(4, "textDocument/hover", 4, 1),
+            request(5, "textDocument/definition", 2, 4),
+        ]);
+
+        assert_eq!(replies[0]["params"]["diagnostics"], json!([]));
+        assert_eq!(
+            replies[1]["result"],
+            json!({
+                "uri": URI,
+                "range": {
+                    "start": { "line": 0, "character": 6 },
+                    "end": { "line": 0, "character": 12 }
+                }
+            })
+        );
+        assert_eq!(
+            replies[2]["result"]["contents"]["value"],
+            "```macaroon\n(defn square (x))\n```\n\nMultiplies x by itself"
+        );
+        assert_eq!(replies[3]["result"], Value::Null);
+        assert_eq!(
+            replies[4]["result"]["contents"]["value"],
+            "```macaroon\ndef: special form\n```"
+        );
+        assert_eq!(replies[5]["result"], Value::Null);
+    }
+
+    #[test]
+    fn test_document_symbols_and_completion() {
+        let replies = run_script(&[
+            open(SOURCE),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "textDocument/documentSymbol",
+                "params": { "textDocument": { "uri": URI } }
+            }),
+            request(2, "textDocument/completion", 5, 3),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "textDocument/formatting", "params": {} }),
+        ]);
+
+        let symbols = replies[1]["result"].as_array().unwrap();
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols[0]["name"], "square");
+        assert_eq!(symbols[0]["kind"], SYMBOL_KIND_FUNCTION);
+        assert_eq!(
+            symbols[0]["range"]["end"],
+            json!({ "line": 2, "character": 10 })
+        );
+        assert_eq!(symbols[1]["name"], "answer");
+        assert_eq!(symbols[1]["kind"], SYMBOL_KIND_VARIABLE);
+
+        let labels: Vec<&str> = replies[2]["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect();
+        assert_eq!(labels, vec!["square"]);
+
+        assert_eq!(replies[3]["error"]["code"], METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_definitions_after_metadata() {
+        let text = "(def ^:private x 1)\n\
+                    (defn ^{:tag number} ^(a b) f (y) y)\n\
+                    (def ^number g (fn (z) z))";
+
+        let found: Vec<(String, String)> = definitions(text)
+            .into_iter()
+            .map(|d| (d.name, d.signature))
+            .collect();
+
+        assert_eq!(
+            found,
+            vec![
+                ("x".to_string(), "(def x)".to_string()),
+                ("f".to_string(), "(defn f (y))".to_string()),
+                ("g".to_string(), "(def g (z))".to_string()),
+            ]
+        );
+        let replies = run_script(&[open(text), request(1, "textDocument/definition", 0, 15)]);
+        assert_eq!(
+            replies[1]["result"]["range"]["start"],
+            json!({ "line": 0, "character": 15 })
+        );
+    }
+
+    #[test]
+    fn test_positions() {
+        let text = "(a\n  \u{1F600}b)";
+        assert_eq!(position_to_offset(text, 1, 4), 9);
+        assert_eq!(
+            offset_to_position(text, 9),
+            json!({ "line": 1, "character": 4 })
+        );
+        assert_eq!(position_to_offset(text, 7, 0), text.len());
+    }
+}
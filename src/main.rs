@@ -1,13 +1,44 @@
 extern crate clap;
+extern crate colored;
+extern crate ctrlc;
 extern crate macaroon;
 extern crate rustyline;
 
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use colored::*;
 use macaroon::ast::{ReaderObj, WriterObj};
 use macaroon::back;
+use macaroon::back::env::SmartEnv;
+use macaroon::check;
+use macaroon::coverage::Coverage;
+use macaroon::debugger::Breakpoint;
+use macaroon::doc;
+use macaroon::fmt;
+use macaroon::golden;
+use macaroon::golden::DiffLine;
+use macaroon::repl;
+use macaroon::repl::{CommandResult, Session};
+use macaroon::server;
+use macaroon::testing;
+use rustyline::completion::Completer;
 use rustyline::error::ReadlineError;
-use rustyline::Editor;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::{Editor, Helper};
+use std::borrow::Cow;
+use std::env;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const HISTORY_FILE_NAME: &str = ".macaroon_history";
 
 fn reader_function() -> Result<String, String> {
     use std::io;
@@ -21,46 +52,668 @@ fn reader_function() -> Result<String, String> {
     }
 }
 
+// Completes and highlights input using the live root environment
+struct ReplHelper {
+    env: SmartEnv,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize) -> Result<(usize, Vec<String>), ReadlineError> {
+        Ok(repl::complete(&self.env, line, pos))
+    }
+}
+
+impl Hinter for ReplHelper {
+    fn hint(&self, _line: &str, _pos: usize) -> Option<String> {
+        None
+    }
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        Cow::Owned(repl::highlight(line, pos))
+    }
+
+    // Any character can change how the line is tokenized, so always rehighlight
+    fn highlight_char(&self, _grapheme: &str) -> bool {
+        true
+    }
+}
+
+impl Helper for ReplHelper {}
+
+// The history file given on the command line, then the one named by MACAROON_HISTORY, then one
+// in the home directory, falling back to the current directory when there is no home directory
+fn history_path(from_args: Option<&str>) -> PathBuf {
+    if let Some(path) = from_args {
+        return PathBuf::from(path);
+    }
+    if let Some(path) = env::var_os("MACAROON_HISTORY") {
+        return PathBuf::from(path);
+    }
+    match env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")) {
+        Some(home) => Path::new(&home).join(HISTORY_FILE_NAME),
+        None => PathBuf::from(HISTORY_FILE_NAME),
+    }
+}
+
+fn run_server(port: &str, config: server::ServerConfig) {
+    let port: u16 = match port.parse() {
+        Ok(port) => port,
+        Err(_) => {
+            eprintln!("Invalid port: {}", port);
+            process::exit(2);
+        }
+    };
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Unable to listen on port {}: {}", port, e);
+            process::exit(1);
+        }
+    };
+
+    // Report the actual port, since port 0 asks for any free one
+    match listener.local_addr() {
+        Ok(address) => println!("Listening on {}", address),
+        Err(_) => println!("Listening on port {}", port),
+    }
+
+    if let Err(e) = server::serve(listener, config) {
+        eprintln!("Server error: {}", e);
+        process::exit(1);
+    }
+}
+
+// Formats the files in place, or only checks them, returning the exit code
+fn run_fmt(matches: &ArgMatches) -> i32 {
+    let check = matches.is_present("check");
+    let paths: Vec<&str> = matches
+        .values_of("PATHS")
+        .map_or(Vec::new(), |v| v.collect());
+
+    if paths.is_empty() {
+        let mut input = String::new();
+        if let Err(e) = io::stdin().read_to_string(&mut input) {
+            eprintln!("Unable to read stdin: {}", e);
+            return 1;
+        }
+        return match fmt::format_source("<stdin>", &input) {
+            Ok(ref formatted) if check && *formatted != input => {
+                println!("Not formatted: <stdin>");
+                1
+            }
+            Ok(..) if check => 0,
+            Ok(formatted) => {
+                print!("{}", formatted);
+                0
+            }
+            Err(errors) => {
+                eprint!("{}", errors);
+                1
+            }
+        };
+    }
+
+    let files = match macaroon::collect_source_files(&paths) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Unable to find source files: {}", e);
+            return 1;
+        }
+    };
+
+    let mut status = 0;
+    for file in files {
+        let name = file.display().to_string();
+        let input = match fs::read_to_string(&file) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("Unable to read file '{}': {}", name, e);
+                status = 1;
+                continue;
+            }
+        };
+
+        match fmt::format_source(&name, &input) {
+            Ok(ref formatted) if *formatted == input => {}
+            Ok(..) if check => {
+                println!("Not formatted: {}", name);
+                status = 1;
+            }
+            Ok(formatted) => match fs::write(&file, formatted) {
+                Ok(()) => println!("Formatted: {}", name),
+                Err(e) => {
+                    eprintln!("Unable to write file '{}': {}", name, e);
+                    status = 1;
+                }
+            },
+            Err(errors) => {
+                eprint!("{}", errors);
+                status = 1;
+            }
+        }
+    }
+    status
+}
+
+fn run_check(matches: &ArgMatches) -> i32 {
+    let paths: Vec<&str> = matches.values_of("PATHS").unwrap().collect();
+    let files = match macaroon::collect_source_files(&paths) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Unable to find source files: {}", e);
+            return 1;
+        }
+    };
+
+    let mut status = 0;
+    for file in files {
+        let name = file.display().to_string();
+        let input = match fs::read_to_string(&file) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("Unable to read file '{}': {}", name, e);
+                status = 1;
+                continue;
+            }
+        };
+
+        match check::check_source(&name, &input) {
+            Ok(diagnostics) => {
+                for diagnostic in &diagnostics {
+                    println!("{}", diagnostic.display());
+                    print!("{}", diagnostic.loc.snippet(&input).unwrap_or_default());
+                }
+                if !diagnostics.is_empty() {
+                    status = 1;
+                }
+            }
+            Err(errors) => {
+                eprint!("{}", errors);
+                status = 1;
+            }
+        }
+    }
+    status
+}
+
+// Runs the golden-output cases in the directory, or updates their expected output, returning the
+// exit code
+fn run_golden(matches: &ArgMatches) -> i32 {
+    let dir = matches.value_of("DIR").unwrap();
+    let update = matches.is_present("update");
+    let jobs = match matches.value_of("jobs") {
+        Some(jobs) => match jobs.parse::<usize>() {
+            Ok(jobs) if jobs > 0 => jobs,
+            _ => {
+                eprintln!("Invalid number of jobs: {}", jobs);
+                return 1;
+            }
+        },
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+
+    let cases = match golden::find_cases(Path::new(dir), matches.value_of("filter")) {
+        Ok(cases) => cases,
+        Err(e) => {
+            eprintln!("Unable to find cases in '{}': {}", dir, e);
+            return 1;
+        }
+    };
+    let count = cases.len();
+
+    let (mut passed, mut failed) = (0, 0);
+    for (source, result) in cases
+        .clone()
+        .into_iter()
+        .zip(golden::run_cases(cases, jobs))
+    {
+        let name = source.display().to_string();
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                println!("{} {}: {}", "FAIL".red().bold(), name, e);
+                failed += 1;
+                continue;
+            }
+        };
+
+        if result.passed() {
+            passed += 1;
+        } else if update {
+            match golden::update(&result) {
+                Ok(()) => println!("Updated: {}", result.expected_path().display()),
+                Err(e) => {
+                    eprintln!(
+                        "Unable to write file '{}': {}",
+                        result.expected_path().display(),
+                        e
+                    );
+                    failed += 1;
+                }
+            }
+        } else {
+            println!("{} {}", "FAIL".red().bold(), name);
+            for line in golden::diff_lines(&result.expected, &result.actual) {
+                match line {
+                    DiffLine::Same(text) => println!("    {}", text),
+                    DiffLine::Expected(text) => println!("  {}", format!("- {}", text).red()),
+                    DiffLine::Actual(text) => println!("  {}", format!("+ {}", text).green()),
+                }
+            }
+            failed += 1;
+        }
+    }
+
+    if update {
+        println!("\n{} updated, {} unchanged", count - passed - failed, passed);
+    } else {
+        println!("\n{} passed, {} failed", passed, failed);
+    }
+    if failed > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+// Writes the coverage as LCOV, returning whether it was written
+fn write_coverage(coverage: &Coverage, path: &str) -> bool {
+    match fs::write(path, coverage.to_lcov()) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Unable to write coverage to '{}': {}", path, e);
+            false
+        }
+    }
+}
+
+// Runs the tests in every test file found, returning the exit code
+fn run_tests(matches: &ArgMatches) -> i32 {
+    let paths: Vec<&str> = matches
+        .values_of("PATHS")
+        .map_or(vec!["."], |v| v.collect());
+    let files = match macaroon::collect_source_files(&paths) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Unable to find source files: {}", e);
+            return 1;
+        }
+    };
+
+    let mut status = 0;
+    let (mut passed, mut failed) = (0, 0);
+    let mut coverage = Coverage::new();
+    for file in files {
+        let name = file.display().to_string();
+        match fs::read_to_string(&file) {
+            Ok(ref input) if !testing::defines_tests(input) => continue,
+            Ok(..) => {}
+            Err(e) => {
+                eprintln!("Unable to read file '{}': {}", name, e);
+                status = 1;
+                continue;
+            }
+        }
+
+        let results = if matches.is_present("coverage") {
+            testing::run_file_with_coverage(&name, &mut coverage)
+        } else {
+            testing::run_file(&name)
+        };
+        match results {
+            Ok(results) => {
+                for result in results {
+                    println!("{}", result.display());
+                    match result.failure {
+                        Some(failure) => {
+                            for line in failure.lines() {
+                                println!("    {}", line);
+                            }
+                            failed += 1;
+                        }
+                        None => passed += 1,
+                    }
+                }
+            }
+            Err(errors) => {
+                eprint!("{}", errors);
+                status = 1;
+            }
+        }
+    }
+
+    println!("\n{} passed, {} failed", passed, failed);
+    if let Some(path) = matches.value_of("coverage") {
+        if !write_coverage(&coverage, path) {
+            status = 1;
+        }
+    }
+    if failed > 0 {
+        status = 1;
+    }
+    status
+}
+
+// Writes the reference page of a module to the output file, or stdout, returning the exit code
+fn run_doc(matches: &ArgMatches) -> i32 {
+    let path = matches.value_of("MODULE").unwrap();
+    let entries = match doc::read_module(path) {
+        Ok(entries) => entries,
+        Err(errors) => {
+            eprint!("{}", errors);
+            return 1;
+        }
+    };
+
+    let page = match matches.value_of("format") {
+        Some("html") => doc::to_html(path, &entries),
+        _ => doc::to_markdown(path, &entries),
+    };
+    match matches.value_of("output") {
+        Some(output) => match fs::write(output, page) {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("Unable to write documentation to '{}': {}", output, e);
+                1
+            }
+        },
+        None => {
+            print!("{}", page);
+            0
+        }
+    }
+}
+
 fn main() {
     let matches = App::new("macaroon")
         .version("0.1.0")
         .about("Macaroon Interpreter")
         .author("Kevin Albrecht <onlyafly@gmail.com>")
+        .setting(AppSettings::TrailingVarArg)
+        // Otherwise a script whose path resembles a subcommand, like testsuite/x.mn, is
+        // rejected as a misspelled subcommand. Such paths still go to INPUT.
+        .setting(AppSettings::AllowExternalSubcommands)
         .arg(
             Arg::with_name("INPUT")
                 .help("*.mn file to interpret")
                 .required(false)
                 .index(1),
+        ).arg(
+            Arg::with_name("ARGS")
+                .help("Arguments made available to the script as *command-line-args*")
+                .required(false)
+                .multiple(true)
+                .index(2),
         ).arg(
             Arg::with_name("x")
                 .short("x")
                 .multiple(false)
                 .help("Executes a script without entering the REPL"),
+        ).arg(
+            Arg::with_name("history")
+                .long("history")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("REPL history file [default: $MACAROON_HISTORY or ~/.macaroon_history]"),
+        ).arg(
+            Arg::with_name("server")
+                .long("server")
+                .value_name("PORT")
+                .takes_value(true)
+                .help(
+                    "Serves a network REPL for editors on localhost port PORT. Anyone who can \
+                     connect may evaluate code, so sessions cannot use the host's files, \
+                     processes or environment variables unless --trust-clients is given",
+                ),
+        ).arg(
+            Arg::with_name("trust-clients")
+                .long("trust-clients")
+                .requires("server")
+                .help("Gives server sessions every primitive except exit, and allows load-file"),
+        ).arg(
+            Arg::with_name("error-format")
+                .long("error-format")
+                .value_name("FORMAT")
+                .takes_value(true)
+                .possible_values(&["human", "json"])
+                .default_value("human")
+                .help("Reports errors in INPUT as text, or as JSON lines on stderr for tools"),
+        ).arg(
+            Arg::with_name("coverage")
+                .long("coverage")
+                .value_name("FILE")
+                .takes_value(true)
+                .requires("INPUT")
+                .help("Writes the line coverage of running INPUT to FILE as LCOV"),
+        ).arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .requires("INPUT")
+                .help("Reports the calls and time of each routine and primitive in running INPUT"),
+        ).arg(
+            Arg::with_name("profile-folded")
+                .long("profile-folded")
+                .value_name("FILE")
+                .takes_value(true)
+                .requires("INPUT")
+                .help("Writes the stacks of calls in running INPUT to FILE, for flamegraphs"),
+        ).arg(
+            Arg::with_name("debug")
+                .long("debug")
+                .requires("INPUT")
+                .help("Debugs INPUT, stopping at its first form"),
+        ).arg(
+            Arg::with_name("break")
+                .long("break")
+                .value_name("SPEC")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .requires("INPUT")
+                .help("Debugs INPUT, stopping at FILE:LINE or on entering the routine named SPEC"),
+        ).subcommand(
+            SubCommand::with_name("fmt")
+                .about("Formats source files in the canonical style")
+                .arg(
+                    Arg::with_name("check")
+                        .long("check")
+                        .help("Reports files that are not formatted, instead of formatting them"),
+                ).arg(
+                    Arg::with_name("PATHS")
+                        .help("Files, or directories to search for *.mn files [default: stdin]")
+                        .multiple(true),
+                ),
+        ).subcommand(
+            SubCommand::with_name("check")
+                .about("Reports likely mistakes in source files without running them")
+                .arg(
+                    Arg::with_name("PATHS")
+                        .help("Files, or directories to search for *.mn files")
+                        .required(true)
+                        .multiple(true),
+                ),
+        ).subcommand(
+            SubCommand::with_name("golden")
+                .about("Runs each foo.mn file in a directory, comparing its output with foo.out")
+                .arg(
+                    Arg::with_name("DIR")
+                        .help("Directory to search for cases")
+                        .required(true),
+                ).arg(
+                    Arg::with_name("update")
+                        .long("update")
+                        .help("Rewrites the .out files of failing cases with their actual output"),
+                ).arg(
+                    Arg::with_name("filter")
+                        .long("filter")
+                        .value_name("TEXT")
+                        .takes_value(true)
+                        .help("Runs only the cases whose paths contain TEXT"),
+                ).arg(
+                    Arg::with_name("jobs")
+                        .long("jobs")
+                        .short("j")
+                        .value_name("N")
+                        .takes_value(true)
+                        .help("Runs N cases at once [default: number of CPUs]"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("test")
+                .about("Runs the tests defined with deftest in source files")
+                .arg(
+                    Arg::with_name("coverage")
+                        .long("coverage")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .help("Writes the line coverage of running the tests to FILE as LCOV"),
+                ).arg(
+                    Arg::with_name("PATHS")
+                        .help("Files, or directories to search for *.mn files [default: .]")
+                        .multiple(true),
+                ),
+        ).subcommand(
+            SubCommand::with_name("doc")
+                .about("Generates a reference page for a module from its docstrings")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .takes_value(true)
+                        .possible_values(&["markdown", "html"])
+                        .default_value("markdown")
+                        .help("Writes the page as markdown or html"),
+                ).arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .help("Writes the page to FILE [default: stdout]"),
+                ).arg(
+                    Arg::with_name("MODULE")
+                        .help("Source file to document")
+                        .required(true),
+                ),
         ).get_matches();
 
-    let history_path = ".macaroon_history";
+    if let Some(fmt_matches) = matches.subcommand_matches("fmt") {
+        process::exit(run_fmt(fmt_matches));
+    }
+    if let Some(check_matches) = matches.subcommand_matches("check") {
+        process::exit(run_check(check_matches));
+    }
+    if let Some(golden_matches) = matches.subcommand_matches("golden") {
+        process::exit(run_golden(golden_matches));
+    }
+    if let Some(test_matches) = matches.subcommand_matches("test") {
+        process::exit(run_tests(test_matches));
+    }
+    if let Some(doc_matches) = matches.subcommand_matches("doc") {
+        process::exit(run_doc(doc_matches));
+    }
 
-    // `()` can be used when no completer is required
-    let mut rl = Editor::<()>::new();
-    if rl.load_history(history_path).is_err() {
-        println!("No previous history.");
+    if let Some(port) = matches.value_of("server") {
+        let config = server::ServerConfig {
+            trust_clients: matches.is_present("trust-clients"),
+        };
+        run_server(port, config);
+        return;
     }
 
+    let history_path = history_path(matches.value_of("history"));
+
     let w = WriterObj::Standard;
     let r = ReaderObj { reader_function };
-    let env = match back::create_root_env(w, r) {
-        Ok(env) => env,
+    let mut session = match Session::new(w, r) {
+        Ok(session) => session,
         Err(_) => panic!("Problem creating root environment"),
     };
 
+    if let Some(script_args) = matches.values_of("ARGS") {
+        session
+            .set_command_line_args(script_args.map(|arg| arg.to_string()).collect())
+            .expect("Problem defining *command-line-args*");
+    }
+
+    let mut rl = Editor::<ReplHelper>::new();
+    rl.set_helper(Some(ReplHelper {
+        env: Rc::clone(&session.env),
+    }));
+    if rl.load_history(&history_path).is_err() {
+        println!("No previous history.");
+    }
+
+    // Ctrl-C interrupts the running evaluation. A second Ctrl-C before the evaluation notices
+    // the first one (for example, while blocked reading input) ends the process. The flag is
+    // replaced when :reset creates a new root environment.
+    let interrupt: Arc<Mutex<Arc<AtomicBool>>> =
+        Arc::new(Mutex::new(back::interrupt_handle(&session.env)));
+    let handler_interrupt = Arc::clone(&interrupt);
+    ctrlc::set_handler(move || {
+        if handler_interrupt
+            .lock()
+            .unwrap()
+            .swap(true, Ordering::SeqCst)
+        {
+            process::exit(130);
+        }
+    }).expect("Problem setting Ctrl-C handler");
+
+    if matches.is_present("coverage") {
+        back::enable_coverage(&session.env);
+    }
+    if matches.is_present("profile") || matches.is_present("profile-folded") {
+        back::enable_profiling(&session.env);
+    }
+    if let Some(specs) = matches.values_of("break") {
+        back::enable_debugger(&session.env, specs.map(Breakpoint::parse).collect());
+    }
+    if matches.is_present("debug") {
+        back::debug_step(&session.env);
+    }
+
     if let Some(input_file) = matches.value_of("INPUT") {
         println!("Loading file: {}", input_file);
-        let output = macaroon::parse_eval_print(
-            Rc::clone(&env),
-            "REPL",
-            &format!("(load \"{}\")", input_file),
-        );
-        println!("{}", output);
+        if matches.value_of("error-format") == Some("json") {
+            match session.eval_file(input_file) {
+                Ok(node) => println!("{}", node.val),
+                Err(reports) => {
+                    for report in reports {
+                        eprintln!("{}", report.to_json());
+                    }
+                    if matches.occurrences_of("x") == 1 {
+                        process::exit(1);
+                    }
+                }
+            }
+        } else {
+            println!("{}", session.load(input_file));
+        }
+
+        if let Some(path) = matches.value_of("coverage") {
+            let coverage = back::take_coverage(&session.env).unwrap_or_default();
+            if !write_coverage(&coverage, path) && matches.occurrences_of("x") == 1 {
+                process::exit(1);
+            }
+        }
+
+        if let Some(profiler) = back::take_profile(&session.env) {
+            if matches.is_present("profile") {
+                eprint!("{}", profiler.report());
+            }
+            if let Some(path) = matches.value_of("profile-folded") {
+                if let Err(e) = fs::write(path, profiler.to_folded()) {
+                    eprintln!("Unable to write stacks to '{}': {}", path, e);
+                    if matches.occurrences_of("x") == 1 {
+                        process::exit(1);
+                    }
+                }
+            }
+        }
     }
 
     // If the -x flag is set, executes a script without entering the REPL
@@ -68,14 +721,47 @@ fn main() {
         return;
     }
 
+    // Lines are accumulated until they form complete input, so that multi-line forms can be
+    // typed or pasted. Each form in the input is then evaluated and printed separately.
+    let mut input = String::new();
     loop {
-        let readline = rl.readline("> ");
+        let prompt = if input.is_empty() { "> " } else { ".. " };
+        let readline = rl.readline(prompt);
         match readline {
             Ok(line) => {
-                rl.add_history_entry(line.as_ref());
+                // When stdin is not a terminal, lines are read with their newline included
+                input.push_str(&line);
+                if !line.ends_with('\n') {
+                    input.push('\n');
+                }
+
+                if macaroon::is_input_incomplete(&input) {
+                    continue;
+                }
+
+                rl.add_history_entry(input.trim_end());
+
+                let results = session.run(&input);
+                input.clear();
 
-                let output = macaroon::parse_eval_print(Rc::clone(&env), "REPL", &line);
-                println!("{}", output);
+                let mut quit = false;
+                for result in results {
+                    match result {
+                        CommandResult::Output(output) => println!("{}", output),
+                        CommandResult::Quit => quit = true,
+                    }
+                }
+                if quit {
+                    break;
+                }
+
+                // Meta-commands such as :reset may have replaced the root environment
+                rl.helper_mut().unwrap().env = Rc::clone(&session.env);
+                *interrupt.lock().unwrap() = back::interrupt_handle(&session.env);
+            }
+            Err(ReadlineError::Interrupted) if !input.is_empty() => {
+                // Abandon the unfinished input, but stay in the session
+                input.clear();
             }
             Err(ReadlineError::Interrupted) => {
                 println!("Pressed CTRL-C... ending session");
@@ -92,5 +778,11 @@ fn main() {
         }
     }
 
-    rl.save_history(history_path).unwrap();
+    if let Err(e) = rl.save_history(&history_path) {
+        eprintln!(
+            "Unable to save history to '{}': {}",
+            history_path.display(),
+            e
+        );
+    }
 }
@@ -0,0 +1,217 @@
+/* An instrumenting profiler for Macaroon code, for --profile. While profiling is enabled in a root
+environment, each call of a routine or primitive is timed, and counted under the routine's name.
+
+Calls of primitives and macros end where they return. A call of a function ends when the
+trampoline it was called in finishes, or when a tail call from it is made in that trampoline,
+as the function's work is then done. */
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ProfileEntry {
+    pub calls: u64,
+    // The time spent in the calls, including the calls they made. The time of a call made from
+    // within a call of the same name is only counted once.
+    pub inclusive: Duration,
+    // The time spent in the calls, excluding the calls they made
+    pub exclusive: Duration,
+}
+
+#[derive(Debug)]
+struct Frame {
+    name: String,
+    // How deeply the trampoline the call was made in is nested
+    depth: usize,
+    // Whether the call ends when a tail call is made from it
+    is_function: bool,
+    start: Instant,
+    in_calls_made: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct Profiler {
+    stack: Vec<Frame>,
+    entries: BTreeMap<String, ProfileEntry>,
+    // The exclusive time of each stack of calls, by the names in the stack, outermost first
+    folded: BTreeMap<Vec<String>, Duration>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            stack: Vec::new(),
+            entries: BTreeMap::new(),
+            folded: BTreeMap::new(),
+        }
+    }
+
+    pub fn enter_function(&mut self, name: String, depth: usize) {
+        let is_tail_call = match self.stack.last() {
+            Some(frame) => frame.is_function && frame.depth == depth,
+            None => false,
+        };
+        if is_tail_call {
+            self.leave();
+        }
+        self.push(name, depth, true);
+    }
+
+    // For calls that end when they return, which must be followed by a call of leave
+    pub fn enter(&mut self, name: String, depth: usize) {
+        self.push(name, depth, false);
+    }
+
+    fn push(&mut self, name: String, depth: usize, is_function: bool) {
+        self.entries
+            .entry(name.clone())
+            .or_default()
+            .calls += 1;
+        self.stack.push(Frame {
+            name,
+            depth,
+            is_function,
+            start: Instant::now(),
+            in_calls_made: Duration::from_secs(0),
+        });
+    }
+
+    pub fn leave(&mut self) {
+        let frame = match self.stack.pop() {
+            Some(frame) => frame,
+            None => return,
+        };
+        let elapsed = frame.start.elapsed();
+        let exclusive = elapsed.checked_sub(frame.in_calls_made).unwrap_or_default();
+        if let Some(caller) = self.stack.last_mut() {
+            caller.in_calls_made += elapsed;
+        }
+
+        let is_recursive = self.stack.iter().any(|f| f.name == frame.name);
+        {
+            let entry = self.entries.get_mut(&frame.name).unwrap();
+            if !is_recursive {
+                entry.inclusive += elapsed;
+            }
+            entry.exclusive += exclusive;
+        }
+
+        let mut names: Vec<String> = self.stack.iter().map(|f| f.name.clone()).collect();
+        names.push(frame.name);
+        *self.folded.entry(names).or_default() += exclusive;
+    }
+
+    // Ends the calls made in the trampoline at the depth, and in those nested within it
+    pub fn leave_depth(&mut self, depth: usize) {
+        while self
+            .stack
+            .last()
+            .is_some_and(|frame| frame.depth >= depth)
+        {
+            self.leave();
+        }
+    }
+
+    pub fn entries(&self) -> &BTreeMap<String, ProfileEntry> {
+        &self.entries
+    }
+
+    // A table of the calls by name, those with the most exclusive time first
+    pub fn report(&self) -> String {
+        let mut entries: Vec<(&String, &ProfileEntry)> = self.entries.iter().collect();
+        entries.sort_by(|a, b| b.1.exclusive.cmp(&a.1.exclusive).then(a.0.cmp(b.0)));
+
+        let mut output = format!(
+            "{:>10} {:>14} {:>14}  {}\n",
+            "calls", "inclusive ms", "exclusive ms", "name"
+        );
+        for (name, entry) in entries {
+            output.push_str(&format!(
+                "{:>10} {:>14.3} {:>14.3}  {}\n",
+                entry.calls,
+                millis(entry.inclusive),
+                millis(entry.exclusive),
+                name
+            ));
+        }
+        output
+    }
+
+    // The stacks of calls in the folded format read by flamegraph tools, each followed by its
+    // exclusive time in microseconds
+    pub fn to_folded(&self) -> String {
+        let mut output = String::new();
+        for (names, time) in &self.folded {
+            let micros = time.as_secs() * 1_000_000 + u64::from(time.subsec_micros());
+            if micros > 0 {
+                output.push_str(&format!("{} {}\n", names.join(";"), micros));
+            }
+        }
+        output
+    }
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1000.0 + f64::from(d.subsec_nanos()) / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use back;
+
+    fn calls(input: &str) -> Vec<(String, u64)> {
+        let env = ::test_env();
+        back::enable_profiling(&env);
+        ::parse_eval_print(env.clone(), "t.mn", input);
+        let profiler = back::take_profile(&env).unwrap();
+        profiler
+            .entries()
+            .iter()
+            .map(|(name, entry)| (name.clone(), entry.calls))
+            .collect()
+    }
+
+    #[test]
+    fn test_counts_calls_of_routines_and_primitives() {
+        assert_eq!(
+            calls(
+                "(def count (fn (n) (if (= n 0) 'done (count (- n 1)))))\n\
+                 (def twice (fn (f x) (f (f x))))\n\
+                 (count 3)\n\
+                 (twice (fn (x) (+ x 1)) 1)"
+            ),
+            vec![
+                ("(fn t.mn:4:12)".to_string(), 2),
+                ("+".to_string(), 2),
+                ("-".to_string(), 3),
+                ("=".to_string(), 4),
+                ("count".to_string(), 4),
+                ("twice".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tail_calls_replace_the_caller() {
+        let mut profiler = Profiler::new();
+        profiler.enter_function("f".to_string(), 1);
+        profiler.enter("+".to_string(), 1);
+        profiler.leave();
+        profiler.enter_function("g".to_string(), 1);
+        profiler.enter_function("h".to_string(), 2);
+        profiler.leave_depth(1);
+
+        let stacks: Vec<Vec<String>> = profiler.folded.keys().cloned().collect();
+        assert_eq!(
+            stacks,
+            vec![
+                vec!["f".to_string()],
+                vec!["f".to_string(), "+".to_string()],
+                vec!["g".to_string()],
+                vec!["g".to_string(), "h".to_string()],
+            ]
+        );
+        assert!(profiler.stack.is_empty());
+    }
+}
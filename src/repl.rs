@@ -0,0 +1,672 @@
+/* Support for interactive editing in the REPL, independent of any line editing library */
+
+use ast::{Node, PrimitiveObj, ReaderObj, RoutineObj, RoutineType, Val, WriterObj};
+use back;
+use back::env::SmartEnv;
+use back::eval::SPECIAL_FORMS;
+use back::runtime_error::RuntimeError;
+use front;
+use front::scanner::{is_symbolic, Scanner};
+use front::syntax_error::SyntaxError;
+use front::tokens::Token;
+use loc::Loc;
+use report::ErrorReport;
+use std::fs;
+use std::rc::Rc;
+use std::time::Instant;
+
+const COLOR_RESET: &str = "\x1b[0m";
+const COLOR_MATCHING_PAREN: &str = "\x1b[1;34m";
+const COLOR_NUMBER: &str = "\x1b[33m";
+const COLOR_STRING: &str = "\x1b[32m";
+const COLOR_SPECIAL_FORM: &str = "\x1b[1;35m";
+const COLOR_CONSTANT: &str = "\x1b[36m";
+const COLOR_COMMENT: &str = "\x1b[90m";
+const COLOR_ERROR: &str = "\x1b[31m";
+
+const HELP: &str = "\
+:doc NAME      Describe what NAME is bound to, and show its documentation
+:type EXPR     Evaluate EXPR and show the type of its value
+:env [PREFIX]  List the bindings of the root environment, optionally only those with PREFIX
+:time EXPR     Evaluate EXPR and show how long it took
+:expand EXPR   Expand the macros at the head of EXPR without evaluating it
+:load PATH     Load a file
+:reload        Load the most recently loaded file again
+:reset         Discard all definitions by starting over with a new root environment
+:quit          End the session
+:help          Show this help
+";
+
+pub enum CommandResult {
+    Output(String),
+    Quit,
+}
+
+// The state of an interactive session: the root environment, and what is needed to recreate it
+pub struct Session {
+    pub env: SmartEnv,
+    writer: WriterObj,
+    reader: ReaderObj,
+    command_line_args: Vec<String>,
+    last_loaded: Option<String>,
+}
+
+impl Session {
+    pub fn new(writer: WriterObj, reader: ReaderObj) -> Result<Session, RuntimeError> {
+        let env = back::create_root_env(writer.clone(), reader.clone())?;
+        let session = Session {
+            env,
+            writer,
+            reader,
+            command_line_args: Vec::new(),
+            last_loaded: None,
+        };
+        session.define_session_bindings()?;
+        Ok(session)
+    }
+
+    pub fn set_command_line_args(&mut self, args: Vec<String>) -> Result<(), RuntimeError> {
+        self.command_line_args = args;
+        self.define_session_bindings()
+    }
+
+    fn define_session_bindings(&self) -> Result<(), RuntimeError> {
+        let mut menv = self.env.borrow_mut();
+
+        let arg_nodes = self
+            .command_line_args
+            .iter()
+            .map(|arg| Node::new(Val::StringVal(arg.clone()), Loc::Unknown))
+            .collect();
+        menv.define(
+            "*command-line-args*",
+            Node::new(Val::List(arg_nodes), Loc::Unknown),
+        )?;
+
+        // The three most recent results, and the most recent error
+        for name in &["*1", "*2", "*3", "*e"] {
+            if !menv.exists(name) {
+                menv.define(name, Node::new(Val::Nil, Loc::Unknown))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Evaluates each top-level form in the input separately, returning the output of each one,
+    // while keeping *1, *2, *3 and *e up to date. Evaluation stops at the first error, whose
+    // message is the final output.
+    pub fn eval_each(&mut self, input: &str) -> Vec<String> {
+        let (mut outputs, error) = self.eval_forms("REPL", input);
+        outputs.extend(error);
+        outputs
+    }
+
+    // Returns the printed value of each form evaluated, and the formatted error that stopped
+    // the evaluation, if any
+    pub fn eval_forms(&mut self, filename: &str, input: &str) -> (Vec<String>, Option<String>) {
+        let nodes = match front::parse(filename, input) {
+            Ok(nodes) => nodes,
+            Err(syntax_errors) => {
+                let message = syntax_errors
+                    .iter()
+                    .map(|e| e.display())
+                    .collect::<Vec<String>>()
+                    .join("; ");
+                self.record_error(message);
+                return (
+                    Vec::new(),
+                    Some(::format_syntax_errors_in_source(&syntax_errors, input)),
+                );
+            }
+        };
+
+        let mut outputs = Vec::new();
+        for node in nodes {
+            match back::eval(Rc::clone(&self.env), vec![node]) {
+                Ok(output_node) => {
+                    outputs.push(format!("{}", output_node.val));
+                    self.record_result(output_node);
+                }
+                Err(runtime_error) => {
+                    self.record_error(runtime_error.display());
+                    let message = ::format_runtime_error_in_source(&runtime_error, filename, input);
+                    return (outputs, Some(message));
+                }
+            }
+        }
+        (outputs, None)
+    }
+
+    fn record_result(&self, node: Node) {
+        let mut menv = self.env.borrow_mut();
+        for &(from, to) in &[("*2", "*3"), ("*1", "*2")] {
+            if let Some(previous) = menv.map.get(from).cloned() {
+                menv.map.insert(to.to_string(), previous);
+            }
+        }
+        menv.map.insert("*1".to_string(), node);
+    }
+
+    fn record_error(&self, message: String) {
+        self.env.borrow_mut().map.insert(
+            "*e".to_string(),
+            Node::new(Val::Error(message), Loc::Unknown),
+        );
+    }
+
+    pub fn load(&mut self, path: &str) -> String {
+        self.last_loaded = Some(path.to_string());
+        ::load_file(Rc::clone(&self.env), path)
+    }
+
+    // Like load, but for tools, in the manner of ::eval_file
+    pub fn eval_file(&mut self, path: &str) -> Result<Node, Vec<ErrorReport>> {
+        self.last_loaded = Some(path.to_string());
+        ::eval_file(Rc::clone(&self.env), path)
+    }
+
+    // Runs either a meta-command (a line starting with ':') or ordinary input, producing one
+    // output for each form evaluated
+    pub fn run(&mut self, input: &str) -> Vec<CommandResult> {
+        let trimmed = input.trim();
+        if let Some(command_line) = trimmed.strip_prefix(':') {
+            vec![self.run_meta_command(command_line)]
+        } else {
+            self.eval_each(input)
+                .into_iter()
+                .map(CommandResult::Output)
+                .collect()
+        }
+    }
+
+    fn run_meta_command(&mut self, command_line: &str) -> CommandResult {
+        let (command, argument) = match command_line.find(char::is_whitespace) {
+            Some(i) => (&command_line[..i], command_line[i..].trim()),
+            None => (command_line, ""),
+        };
+
+        let output = match (command, argument) {
+            ("quit", _) | ("q", _) => return CommandResult::Quit,
+            ("help", _) => HELP.to_string(),
+            ("doc", name) if !name.is_empty() => match documentation(&self.env, name) {
+                Some(doc) => format!("{}\n{}", describe(&self.env, name), doc),
+                None => describe(&self.env, name),
+            },
+            ("type", expr) if !expr.is_empty() => {
+                self.eval_then(expr, |n| n.type_name().unwrap().to_string())
+            }
+            ("env", prefix) => list_bindings(&self.env, prefix),
+            ("time", expr) if !expr.is_empty() => {
+                let start = Instant::now();
+                let outputs = self.eval_each(expr);
+                format!(
+                    "{}\nElapsed time: {:?}",
+                    outputs.join("\n"),
+                    start.elapsed()
+                )
+            }
+            ("expand", expr) if !expr.is_empty() => match parse_single(expr) {
+                Ok(node) => match back::macroexpand(Rc::clone(&self.env), node) {
+                    Ok(expanded) => format!("{}", expanded.val),
+                    Err(e) => ::format_runtime_error(&e),
+                },
+                Err(message) => message,
+            },
+            ("load", path) if !path.is_empty() => self.load(path.trim_matches('"')),
+            ("reload", _) => match self.last_loaded.clone() {
+                Some(path) => self.load(&path),
+                None => "No file has been loaded yet".to_string(),
+            },
+            ("reset", _) => match back::create_root_env(self.writer.clone(), self.reader.clone()) {
+                Ok(env) => {
+                    self.env = env;
+                    match self.define_session_bindings() {
+                        Ok(()) => "Started over with a new root environment".to_string(),
+                        Err(e) => ::format_runtime_error(&e),
+                    }
+                }
+                Err(e) => ::format_runtime_error(&e),
+            },
+            ("doc", _) | ("type", _) | ("time", _) | ("expand", _) | ("load", _) => {
+                format!("Missing argument for :{}. Type :help for usage.", command)
+            }
+            _ => format!(
+                "Unknown command :{}. Type :help for a list of commands.",
+                command
+            ),
+        };
+
+        CommandResult::Output(output)
+    }
+
+    fn eval_then<F: Fn(&Node) -> String>(&self, expr: &str, f: F) -> String {
+        let node = match parse_single(expr) {
+            Ok(node) => node,
+            Err(message) => return message,
+        };
+        match back::eval(Rc::clone(&self.env), vec![node]) {
+            Ok(output) => f(&output),
+            Err(e) => ::format_runtime_error(&e),
+        }
+    }
+}
+
+fn parse_single(input: &str) -> Result<Node, String> {
+    match front::parse("REPL", input) {
+        Ok(mut nodes) => {
+            if nodes.len() == 1 {
+                Ok(nodes.remove(0))
+            } else {
+                Err(format!(
+                    "Expected exactly one form, but got {}",
+                    nodes.len()
+                ))
+            }
+        }
+        Err(errors) => Err(::format_syntax_errors(&errors)),
+    }
+}
+
+// Describes the value bound to a name, or the special form it refers to
+pub fn describe(env: &SmartEnv, name: &str) -> String {
+    if SPECIAL_FORMS.contains(&name) {
+        return format!("{}: special form", name);
+    }
+
+    let node = match env.borrow().get(name) {
+        Some(node) => node,
+        None => return format!("Undefined name: {}", name),
+    };
+
+    match node.val {
+        Val::Primitive(PrimitiveObj {
+            min_arity,
+            max_arity,
+            ..
+        }) => format!(
+            "{}: primitive taking {}",
+            name,
+            describe_arity(min_arity, max_arity)
+        ),
+        Val::Routine(RoutineObj {
+            ref params,
+            ref routine_type,
+            ref return_type,
+            ..
+        }) => {
+            let kind = match routine_type {
+                RoutineType::Function => "function",
+                RoutineType::Macro => "macro",
+            };
+            let mut signature = vec![name.to_string()];
+            signature.extend(params.iter().map(|p| match p.type_annotation() {
+                Some(type_name) => format!("^{} {}", type_name, p.val),
+                None => format!("{}", p.val),
+            }));
+            match return_type {
+                Some(type_name) => format!(
+                    "({}): {} returning {}",
+                    signature.join(" "),
+                    kind,
+                    type_name
+                ),
+                None => format!("({}): {}", signature.join(" "), kind),
+            }
+        }
+        ref v => format!("{}: {} {}", name, v.type_name().unwrap(), v),
+    }
+}
+
+// The documentation of the routine or primitive bound to the name, if it has any
+pub fn documentation(env: &SmartEnv, name: &str) -> Option<String> {
+    match env.borrow().get(name)?.val {
+        Val::Routine(RoutineObj { doc, .. }) => doc,
+        Val::Primitive(PrimitiveObj { doc: Some(doc), .. }) => Some(doc.doc.to_string()),
+        _ => None,
+    }
+}
+
+fn describe_arity(min: isize, max: isize) -> String {
+    if max == -1 {
+        format!("at least {} arg(s)", min)
+    } else if min == max {
+        format!("{} arg(s)", min)
+    } else {
+        format!("between {} and {} arg(s)", min, max)
+    }
+}
+
+// Lists the bindings defined directly in the environment, sorted by name
+pub fn list_bindings(env: &SmartEnv, prefix: &str) -> String {
+    let e = env.borrow();
+    let mut names: Vec<&String> = e.map.keys().filter(|k| k.starts_with(prefix)).collect();
+    names.sort();
+
+    let lines: Vec<String> = names
+        .into_iter()
+        .map(|name| format!("{} = {}", name, e.map[name].val))
+        .collect();
+    lines.join("\n")
+}
+
+// Returns the byte offset where the completed text starts, and the candidates to replace it with.
+// Inside the string of a (load "...") form, file paths are completed. Everywhere else, the names
+// visible from the environment and the special forms are completed.
+pub fn complete(env: &SmartEnv, line: &str, pos: usize) -> (usize, Vec<String>) {
+    let before = &line[..pos];
+
+    if let Some(path_start) = load_path_start(before) {
+        return (path_start, complete_path(&before[path_start..]));
+    }
+
+    let start = before
+        .char_indices()
+        .rev()
+        .take_while(|&(_, ch)| is_symbolic(ch))
+        .last()
+        .map_or(pos, |(i, _)| i);
+    let prefix = &before[start..];
+
+    let mut candidates: Vec<String> = env
+        .borrow()
+        .names()
+        .into_iter()
+        .chain(SPECIAL_FORMS.iter().map(|s| s.to_string()))
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+
+    (start, candidates)
+}
+
+// If the input ends inside the unterminated string argument of a load form, returns the byte
+// offset just after the opening quote
+fn load_path_start(before: &str) -> Option<usize> {
+    let mut s = Scanner::new("REPL", before);
+    let mut previous = Vec::new();
+
+    loop {
+        match s.next() {
+            Ok(Token::EndOfFile) => return None,
+            Ok(t) => previous.push(t),
+            Err(SyntaxError::UnterminatedStringLiteral(..)) => {
+                let (quote_start, _) = s.span();
+                let n = previous.len();
+                let in_load = n >= 2
+                    && previous[n - 2] == Token::LeftParen
+                    && previous[n - 1] == Token::Symbol("load".to_string());
+                return if in_load { Some(quote_start + 1) } else { None };
+            }
+            Err(_) => previous.push(Token::Error),
+        }
+    }
+}
+
+fn complete_path(partial: &str) -> Vec<String> {
+    let (dir, file_prefix) = match partial.rfind('/') {
+        Some(i) => (&partial[..i + 1], &partial[i + 1..]),
+        None => ("", partial),
+    };
+
+    let entries = match fs::read_dir(if dir.is_empty() { "." } else { dir }) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut candidates = Vec::new();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let hidden = name.starts_with('.') && !file_prefix.starts_with('.');
+        if hidden || !name.starts_with(file_prefix) {
+            continue;
+        }
+
+        let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+        let suffix = if is_dir { "/" } else { "" };
+        candidates.push(format!("{}{}{}", dir, name, suffix));
+    }
+    candidates.sort();
+
+    candidates
+}
+
+// Colors the line using the tokens produced by the scanner. The paren under or just before the
+// cursor is highlighted together with its match.
+pub fn highlight(line: &str, pos: usize) -> String {
+    let matching = matching_paren(line, pos);
+    let mut output = String::new();
+    let mut s = Scanner::new("REPL", line);
+    let mut copied_up_to = 0;
+
+    loop {
+        let result = s.next();
+        let (start, end) = s.span();
+
+        // Whatever the scanner skipped over is whitespace and comments
+        let gap = &line[copied_up_to..start];
+        if gap.trim().is_empty() {
+            output.push_str(gap);
+        } else {
+            push_colored(&mut output, COLOR_COMMENT, gap);
+        }
+
+        let text = &line[start..end];
+        let color = match result {
+            Ok(Token::EndOfFile) => break,
+            Ok(Token::LeftParen) | Ok(Token::RightParen) => match matching {
+                Some((a, b)) if start == a || start == b => Some(COLOR_MATCHING_PAREN),
+                _ => None,
+            },
+            Ok(Token::Number(..)) => Some(COLOR_NUMBER),
+            Ok(Token::StringLiteral(..)) | Ok(Token::Character { .. }) => Some(COLOR_STRING),
+            Ok(Token::Symbol(ref name)) if SPECIAL_FORMS.contains(&name.as_ref()) => {
+                Some(COLOR_SPECIAL_FORM)
+            }
+            Ok(Token::Symbol(ref name)) if name == "true" || name == "false" || name == "nil" => {
+                Some(COLOR_CONSTANT)
+            }
+            Ok(Token::Symbol(ref name)) if name.starts_with(':') => Some(COLOR_CONSTANT),
+            Ok(Token::Symbol(..)) | Ok(Token::Error) => None,
+            Ok(Token::LeftBrace) | Ok(Token::RightBrace) => None,
+            Ok(Token::SingleQuote) | Ok(Token::Caret) => Some(COLOR_CONSTANT),
+            Err(SyntaxError::UnterminatedStringLiteral(..)) => Some(COLOR_STRING),
+            Err(SyntaxError::UnterminatedMultilineComment(..)) => Some(COLOR_COMMENT),
+            Err(..) => Some(COLOR_ERROR),
+        };
+
+        match color {
+            Some(c) => push_colored(&mut output, c, text),
+            None => output.push_str(text),
+        }
+        copied_up_to = end;
+    }
+
+    output
+}
+
+fn push_colored(output: &mut String, color: &str, text: &str) {
+    output.push_str(color);
+    output.push_str(text);
+    output.push_str(COLOR_RESET);
+}
+
+// Finds the byte offsets of the paren under the cursor (or, failing that, just before it) and
+// its matching paren. Parens inside strings, characters, and comments are ignored.
+pub fn matching_paren(line: &str, pos: usize) -> Option<(usize, usize)> {
+    let mut pairs = Vec::new();
+    let mut open = Vec::new();
+    let mut s = Scanner::new("REPL", line);
+
+    loop {
+        match s.next() {
+            Ok(Token::EndOfFile) => break,
+            Ok(Token::LeftParen) => open.push(s.span().0),
+            Ok(Token::RightParen) => {
+                if let Some(start) = open.pop() {
+                    pairs.push((start, s.span().0));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let find = |offset: usize| {
+        pairs
+            .iter()
+            .find(|&&(a, b)| a == offset || b == offset)
+            .cloned()
+    };
+
+    match find(pos) {
+        Some(pair) => Some(pair),
+        None if pos > 0 => find(pos - 1),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complete_names_and_special_forms() {
+        let env = ::test_env();
+        ::parse_eval_print(::std::rc::Rc::clone(&env), "REPL", "(def first-thing 1)");
+
+        assert_eq!(
+            complete(&env, "(fi", 3),
+            (
+                1,
+                vec![
+                    "file-exists?".to_string(),
+                    "first".to_string(),
+                    "first-thing".to_string()
+                ]
+            )
+        );
+        assert_eq!(
+            complete(&env, "(macroex", 8),
+            (1, vec!["macroexpand1".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_complete_load_path() {
+        let env = ::test_env();
+
+        assert_eq!(
+            complete(&env, "(load \"testsuite/load", 21),
+            (7, vec!["testsuite/loading/".to_string()])
+        );
+        assert_eq!(
+            complete(&env, "(println \"testsuite/load", 24).1,
+            Vec::<String>::new()
+        );
+    }
+
+    fn session() -> Session {
+        Session::new(WriterObj::Sink, ::test_reader()).unwrap()
+    }
+
+    fn run_one(session: &mut Session, input: &str) -> String {
+        match session.run(input).remove(0) {
+            CommandResult::Output(s) => s,
+            CommandResult::Quit => ":quit".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_meta_commands() {
+        let mut s = session();
+        run_one(&mut s, "(def answer 42)");
+
+        assert_eq!(run_one(&mut s, ":type answer"), "number");
+        assert_eq!(run_one(&mut s, ":doc answer"), "answer: number 42");
+        assert_eq!(
+            run_one(&mut s, ":doc first"),
+            "first: primitive taking 1 arg(s)\nThe first element of a list or string, or nil if it is empty"
+        );
+        assert_eq!(run_one(&mut s, ":doc if"), "if: special form");
+        run_one(&mut s, "(def add (fn ^number (^number a b) (+ a b)))");
+        assert_eq!(
+            run_one(&mut s, ":doc add"),
+            "(add ^number a b): function returning number"
+        );
+        assert_eq!(run_one(&mut s, ":env ans"), "answer = 42");
+        assert_eq!(
+            run_one(&mut s, ":bogus"),
+            "Unknown command :bogus. Type :help for a list of commands."
+        );
+        assert_eq!(run_one(&mut s, ":quit"), ":quit");
+    }
+
+    #[test]
+    fn test_result_history() {
+        let mut s = session();
+        assert_eq!(run_one(&mut s, "*1"), "nil");
+
+        s.run("1 2 3 4");
+        assert_eq!(s.run("(list *1 *2 *3)").len(), 1);
+        assert_eq!(run_one(&mut s, "*1"), "(4 3 2)");
+        assert_eq!(run_one(&mut s, "*e"), "nil");
+
+        run_one(&mut s, "(undefined-thing)");
+        assert_eq!(
+            run_one(&mut s, "*e"),
+            "#error<Undefined name: undefined-thing>"
+        );
+        assert_eq!(run_one(&mut s, ":type *e"), "error");
+        assert_eq!(
+            run_one(&mut s, "*1"),
+            "#error<Undefined name: undefined-thing>"
+        );
+    }
+
+    #[test]
+    fn test_expand_load_and_reset() {
+        let mut s = session();
+
+        assert_eq!(
+            run_one(&mut s, ":load examples/prelude.mn"),
+            "\"Prelude version 2018-10-13\""
+        );
+        assert_eq!(
+            run_one(&mut s, ":expand (defn f (x) x)"),
+            "(def f (fn (x) (begin x)))"
+        );
+        assert_eq!(
+            run_one(&mut s, ":expand (defn f (x) \"Returns x\" x)"),
+            "(def f (fn (x) \"Returns x\" (begin x)))"
+        );
+        assert_eq!(
+            run_one(&mut s, ":doc defn"),
+            "(defn name args &rest exps): macro\nDefines a function. A string before the rest of its body documents the function."
+        );
+
+        run_one(&mut s, ":reset");
+        assert_eq!(run_one(&mut s, ":doc defn"), "Undefined name: defn");
+
+        assert_eq!(run_one(&mut s, ":reload"), "\"Prelude version 2018-10-13\"");
+        assert_eq!(
+            run_one(&mut s, ":doc defn"),
+            "(defn name args &rest exps): macro\nDefines a function. A string before the rest of its body documents the function."
+        );
+    }
+
+    #[test]
+    fn test_matching_paren() {
+        assert_eq!(matching_paren("(a (b) \")\")", 0), Some((0, 10)));
+        assert_eq!(matching_paren("(a (b) \")\")", 6), Some((3, 5)));
+        assert_eq!(matching_paren("(a (b) \")\")", 1), Some((0, 10)));
+        assert_eq!(matching_paren("(a b", 4), None);
+    }
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(
+            highlight("(if 1 \"s\") ; c", 0),
+            "\x1b[1;34m(\x1b[0m\x1b[1;35mif\x1b[0m \x1b[33m1\x1b[0m \x1b[32m\"s\"\x1b[0m\x1b[1;34m)\x1b[0m\x1b[90m ; c\x1b[0m"
+        );
+    }
+}
@@ -0,0 +1,136 @@
+/* Errors in a form meant for tools, such as editors and CI, rather than for people. Each error
+has a code that identifies its kind and never changes, so tools can rely on it where they can't
+rely on the wording of messages. */
+
+use back::runtime_error::RuntimeError;
+use front::syntax_error::SyntaxError;
+use loc::Loc;
+use serde_json::Value;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ErrorKind {
+    Syntax,
+    Runtime,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ErrorReport {
+    pub kind: ErrorKind,
+    pub code: &'static str,
+    pub message: String,
+    pub loc: Loc,
+    // For runtime errors, the locations of the evaluations that the error happened within,
+    // innermost first
+    pub stack: Vec<Loc>,
+}
+
+impl ErrorReport {
+    pub fn from_syntax_error(syntax_error: &SyntaxError) -> ErrorReport {
+        ErrorReport {
+            kind: ErrorKind::Syntax,
+            code: syntax_error.code(),
+            message: syntax_error.display(),
+            loc: syntax_error.loc(),
+            stack: Vec::new(),
+        }
+    }
+
+    // The stack is that of the context the error was raised in, whose innermost evaluation is
+    // often the erroneous code itself, which is left out
+    pub fn from_runtime_error(runtime_error: &RuntimeError, stack: Vec<Loc>) -> ErrorReport {
+        let loc = runtime_error.loc();
+        let stack = stack
+            .into_iter()
+            .skip_while(|frame| *frame == loc)
+            .collect();
+        ErrorReport {
+            kind: ErrorKind::Runtime,
+            code: runtime_error.code(),
+            message: runtime_error.display(),
+            loc,
+            stack,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let kind = match self.kind {
+            ErrorKind::Syntax => "syntax",
+            ErrorKind::Runtime => "runtime",
+        };
+        json!({
+            "kind": kind,
+            "code": self.code,
+            "message": self.message,
+            "span": span_to_json(&self.loc),
+            "stack": self.stack.iter().map(span_to_json).collect::<Vec<Value>>()
+        })
+    }
+}
+
+// Spans are null when the location is unknown, such as for code produced by a macro
+fn span_to_json(loc: &Loc) -> Value {
+    match loc {
+        Loc::File {
+            filename,
+            line,
+            column,
+            end_line,
+            end_column,
+        } => json!({
+            "file": filename,
+            "line": line,
+            "column": column,
+            "end_line": end_line,
+            "end_column": end_column
+        }),
+        Loc::Unknown => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reports(input: &str) -> Vec<Value> {
+        match ::parse_eval(::test_env(), "t.mn", input) {
+            Ok(_) => Vec::new(),
+            Err(reports) => reports.iter().map(ErrorReport::to_json).collect(),
+        }
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        assert_eq!(
+            reports("(def x 1)\n  \"abc"),
+            vec![json!({
+                "kind": "syntax",
+                "code": "E0007",
+                "message": "Unterminated string literal",
+                "span": { "file": "t.mn", "line": 2, "column": 3, "end_line": 2, "end_column": 7 },
+                "stack": []
+            })]
+        );
+    }
+
+    #[test]
+    fn test_runtime_errors_have_a_stack() {
+        let span = |column, end_column| json!({ "file": "t.mn", "line": 2, "column": column, "end_line": 2, "end_column": end_column });
+        assert_eq!(
+            reports("(def f (fn (x) (+ x nope)))\n(+ 1 (f 2))"),
+            vec![json!({
+                "kind": "runtime",
+                "code": "E0103",
+                "message": "Undefined name: nope",
+                "span": {
+                    "file": "t.mn", "line": 1, "column": 21, "end_line": 1, "end_column": 25
+                },
+                "stack": [span(6, 11), span(1, 12)]
+            })]
+        );
+
+        // A later error doesn't report the stack of an earlier one that was handled by load
+        let reports = reports("(load \"testsuite/basic/107-undefined-name.mn\")\n(car)");
+        assert_eq!(reports[0]["message"], "Undefined name: car");
+        assert_eq!(reports[0]["stack"], json!([span(1, 6)]));
+    }
+}
@@ -0,0 +1,580 @@
+/* A network REPL for editor integration.
+
+Clients send one JSON object per line and receive one JSON object per line in reply. Every
+request has an "op", and may have an "id", which is copied into the reply so that replies can be
+matched to requests. Requests other than "clone" and "describe" name a "session"; evaluations in
+different sessions do not share definitions. Requests without a session use a default one.
+
+    {"op": "clone"}                                  => {"new-session": "1", ...}
+    {"op": "close", "session": "1"}
+    {"op": "eval", "session": "1", "code": "(+ 1 2)"} => {"values": ["3"], "out": "", ...}
+    {"op": "load-file", "session": "1", "path": "examples/prelude.mn"}
+    {"op": "complete", "session": "1", "line": "(pri", "pos": 4}
+    {"op": "interrupt", "session": "1"}
+    {"op": "describe"}
+
+Replies have a "status" of "done", or "error" along with an "error" message. Replies to eval and
+load-file have the printed "values" of the forms evaluated, what was written to *writer* and
+*error-writer* as "out" and "err", and the runtime or syntax error that stopped the evaluation,
+if any, as "error".
+
+All sessions are evaluated on the thread that calls serve, one request at a time. Interrupts are
+handled by the connection's own thread, so that they can stop an evaluation that is running.
+
+Anyone who can connect to the server can evaluate code in it, so by default sessions only have
+the primitives a sandbox allows, and load-file is refused: clients cannot touch the host's files,
+processes or environment variables. A server that trusts its clients gives sessions every other
+primitive too, except exit, as it would end the server along with every other session; a client
+ends its session with close instead. */
+
+use ast::{Node, ReaderObj, Val, WriterObj};
+use back;
+use back::sandbox::SandboxConfig;
+use loc::Loc;
+use repl;
+use repl::Session;
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const OPS: &[&str] = &[
+    "clone",
+    "close",
+    "ls-sessions",
+    "eval",
+    "load-file",
+    "complete",
+    "interrupt",
+    "describe",
+];
+
+const DEFAULT_SESSION: &str = "default";
+
+type Connection = Arc<Mutex<TcpStream>>;
+type InterruptFlags = Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>;
+
+struct Request {
+    message: Map<String, Value>,
+    connection: Connection,
+}
+
+struct ServerSession {
+    session: Session,
+    out: Rc<RefCell<Vec<u8>>>,
+    err: Rc<RefCell<Vec<u8>>>,
+}
+
+// What the clients of a server may do
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct ServerConfig {
+    // Whether sessions may use the host's files, processes and environment variables
+    pub trust_clients: bool,
+}
+
+struct Server {
+    config: ServerConfig,
+    sessions: HashMap<String, ServerSession>,
+    interrupt_flags: InterruptFlags,
+    next_session_id: u64,
+}
+
+fn reader_function() -> Result<String, String> {
+    Err("Input cannot be read in a server session".to_string())
+}
+
+// Accepts connections until the listener fails, evaluating their requests on this thread
+pub fn serve(listener: TcpListener, config: ServerConfig) -> io::Result<()> {
+    let interrupt_flags: InterruptFlags = Arc::new(Mutex::new(HashMap::new()));
+    let (sender, receiver) = channel::<Request>();
+
+    let acceptor_flags = Arc::clone(&interrupt_flags);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let sender = sender.clone();
+            let flags = Arc::clone(&acceptor_flags);
+            thread::spawn(move || {
+                let _ = read_requests(stream, sender, flags);
+            });
+        }
+    });
+
+    let mut server = Server {
+        config,
+        sessions: HashMap::new(),
+        interrupt_flags,
+        next_session_id: 1,
+    };
+    server.run(receiver);
+    Ok(())
+}
+
+// Reads the requests of one connection, passing them on to be evaluated, except for interrupts,
+// which must be handled while an evaluation is running
+fn read_requests(
+    stream: TcpStream,
+    sender: Sender<Request>,
+    interrupt_flags: InterruptFlags,
+) -> io::Result<()> {
+    let connection: Connection = Arc::new(Mutex::new(stream.try_clone()?));
+    let reader = BufReader::new(stream);
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let message = match serde_json::from_str::<Value>(&line) {
+            Ok(Value::Object(message)) => message,
+            Ok(_) => {
+                send(
+                    &connection,
+                    error_reply(&Map::new(), "Requests must be JSON objects"),
+                )?;
+                continue;
+            }
+            Err(e) => {
+                let error = format!("Invalid request: {}", e);
+                send(&connection, error_reply(&Map::new(), &error))?;
+                continue;
+            }
+        };
+
+        if op(&message) == "interrupt" {
+            let name = session_name(&message);
+            let reply = match interrupt_flags.lock().unwrap().get(&name) {
+                Some(flag) => {
+                    flag.store(true, Ordering::SeqCst);
+                    done_reply(&message)
+                }
+                None => error_reply(&message, &format!("Unknown session: {}", name)),
+            };
+            send(&connection, reply)?;
+            continue;
+        }
+
+        let request = Request {
+            message,
+            connection: Arc::clone(&connection),
+        };
+        if sender.send(request).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+impl Server {
+    fn run(&mut self, receiver: Receiver<Request>) {
+        for request in receiver {
+            let reply = self.handle(&request.message);
+            // A client that has gone away does not stop the server
+            let _ = send(&request.connection, reply);
+        }
+    }
+
+    fn handle(&mut self, message: &Map<String, Value>) -> Map<String, Value> {
+        match op(message).as_ref() {
+            "clone" => {
+                let name = self.next_session_id.to_string();
+                self.next_session_id += 1;
+                match self.create_session(&name) {
+                    Ok(()) => {
+                        let mut reply = done_reply(message);
+                        reply.insert("new-session".to_string(), Value::String(name));
+                        reply
+                    }
+                    Err(error) => error_reply(message, &error),
+                }
+            }
+            "close" => {
+                let name = session_name(message);
+                self.interrupt_flags.lock().unwrap().remove(&name);
+                match self.sessions.remove(&name) {
+                    Some(..) => done_reply(message),
+                    None => error_reply(message, &format!("Unknown session: {}", name)),
+                }
+            }
+            "ls-sessions" => {
+                let mut names: Vec<Value> = self
+                    .sessions
+                    .keys()
+                    .map(|name| Value::String(name.clone()))
+                    .collect();
+                names.sort_by(|a, b| a.as_str().cmp(&b.as_str()));
+                let mut reply = done_reply(message);
+                reply.insert("sessions".to_string(), Value::Array(names));
+                reply
+            }
+            "eval" => match string_field(message, "code") {
+                Some(code) => self.eval(message, "REPL", &code),
+                None => error_reply(message, "Missing field: code"),
+            },
+            "load-file" if !self.config.trust_clients => {
+                error_reply(message, "Loading files requires a server that trusts its clients")
+            }
+            "load-file" => match string_field(message, "path") {
+                Some(path) => match fs::read_to_string(&path) {
+                    Ok(contents) => self.eval(message, &path, &contents),
+                    Err(e) => {
+                        error_reply(message, &format!("Unable to load file '{}': {}", path, e))
+                    }
+                },
+                None => error_reply(message, "Missing field: path"),
+            },
+            "complete" => match string_field(message, "line") {
+                Some(line) => self.complete(message, &line),
+                None => error_reply(message, "Missing field: line"),
+            },
+            "describe" => {
+                let ops = OPS.iter().map(|op| Value::String(op.to_string())).collect();
+                let mut reply = done_reply(message);
+                reply.insert("ops".to_string(), Value::Array(ops));
+                reply
+            }
+            other => error_reply(message, &format!("Unknown op: {}", other)),
+        }
+    }
+
+    fn create_session(&mut self, name: &str) -> Result<(), String> {
+        let out = Rc::new(RefCell::new(Vec::new()));
+        let err = Rc::new(RefCell::new(Vec::new()));
+        let reader = ReaderObj { reader_function };
+
+        let session =
+            Session::new(WriterObj::Buffer(Rc::clone(&out)), reader).map_err(|e| e.display())?;
+        session
+            .env
+            .borrow_mut()
+            .define(
+                "*error-writer*",
+                Node::new(
+                    Val::Writer(WriterObj::Buffer(Rc::clone(&err))),
+                    Loc::Unknown,
+                ),
+            )
+            .map_err(|e| e.display())?;
+        if self.config.trust_clients {
+            session.env.borrow_mut().map.remove("exit");
+        } else {
+            let sandbox = SandboxConfig::default();
+            session.env.borrow_mut().map.retain(|name, node| match node.val {
+                Val::Primitive(..) => sandbox.allows_primitive(name),
+                _ => true,
+            });
+        }
+
+        self.interrupt_flags
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), back::interrupt_handle(&session.env));
+        self.sessions
+            .insert(name.to_string(), ServerSession { session, out, err });
+        Ok(())
+    }
+
+    // Finds the session named by the request, creating the default session on first use
+    fn session(&mut self, message: &Map<String, Value>) -> Result<&mut ServerSession, String> {
+        let name = session_name(message);
+        if !self.sessions.contains_key(&name) {
+            if name == DEFAULT_SESSION {
+                self.create_session(&name)?;
+            } else {
+                return Err(format!("Unknown session: {}", name));
+            }
+        }
+        Ok(self.sessions.get_mut(&name).unwrap())
+    }
+
+    fn eval(
+        &mut self,
+        message: &Map<String, Value>,
+        filename: &str,
+        code: &str,
+    ) -> Map<String, Value> {
+        let server_session = match self.session(message) {
+            Ok(server_session) => server_session,
+            Err(error) => return error_reply(message, &error),
+        };
+
+        let (values, error) = server_session.session.eval_forms(filename, code);
+        let out = take_output(&server_session.out);
+        let err = take_output(&server_session.err);
+
+        let mut reply = match error {
+            Some(error) => error_reply(message, error.trim_end()),
+            None => done_reply(message),
+        };
+        reply.insert(
+            "values".to_string(),
+            Value::Array(values.into_iter().map(Value::String).collect()),
+        );
+        reply.insert("out".to_string(), Value::String(out));
+        reply.insert("err".to_string(), Value::String(err));
+        reply
+    }
+
+    fn complete(&mut self, message: &Map<String, Value>, line: &str) -> Map<String, Value> {
+        let pos = match message.get("pos").and_then(Value::as_u64) {
+            Some(pos) => pos as usize,
+            None => line.len(),
+        };
+        if pos > line.len() || !line.is_char_boundary(pos) {
+            return error_reply(message, &format!("Invalid position: {}", pos));
+        }
+
+        let env = match self.session(message) {
+            Ok(server_session) => Rc::clone(&server_session.session.env),
+            Err(error) => return error_reply(message, &error),
+        };
+        let (start, candidates) = repl::complete(&env, line, pos);
+
+        let mut reply = done_reply(message);
+        reply.insert("start".to_string(), Value::from(start));
+        reply.insert(
+            "completions".to_string(),
+            Value::Array(candidates.into_iter().map(Value::String).collect()),
+        );
+        reply
+    }
+}
+
+fn take_output(buffer: &Rc<RefCell<Vec<u8>>>) -> String {
+    let bytes: Vec<u8> = buffer.borrow_mut().drain(..).collect();
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+fn op(message: &Map<String, Value>) -> String {
+    string_field(message, "op").unwrap_or_default()
+}
+
+fn session_name(message: &Map<String, Value>) -> String {
+    string_field(message, "session").unwrap_or_else(|| DEFAULT_SESSION.to_string())
+}
+
+fn string_field(message: &Map<String, Value>, field: &str) -> Option<String> {
+    message
+        .get(field)
+        .and_then(Value::as_str)
+        .map(|s| s.to_string())
+}
+
+fn reply(message: &Map<String, Value>, status: &str) -> Map<String, Value> {
+    let mut reply = Map::new();
+    for field in &["id", "session"] {
+        if let Some(value) = message.get(*field) {
+            reply.insert(field.to_string(), value.clone());
+        }
+    }
+    reply.insert("status".to_string(), Value::String(status.to_string()));
+    reply
+}
+
+fn done_reply(message: &Map<String, Value>) -> Map<String, Value> {
+    reply(message, "done")
+}
+
+fn error_reply(message: &Map<String, Value>, error: &str) -> Map<String, Value> {
+    let mut reply = reply(message, "error");
+    reply.insert("error".to_string(), Value::String(error.to_string()));
+    reply
+}
+
+fn send(connection: &Connection, reply: Map<String, Value>) -> io::Result<()> {
+    let mut stream = connection.lock().unwrap();
+    writeln!(stream, "{}", Value::Object(reply))?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::SocketAddr;
+
+    fn start_server() -> SocketAddr {
+        start_server_with_config(ServerConfig::default())
+    }
+
+    fn start_server_with_config(config: ServerConfig) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener, config));
+        address
+    }
+
+    struct Client {
+        stream: TcpStream,
+        reader: BufReader<TcpStream>,
+    }
+
+    impl Client {
+        fn connect(address: SocketAddr) -> Client {
+            let stream = TcpStream::connect(address).unwrap();
+            let reader = BufReader::new(stream.try_clone().unwrap());
+            Client { stream, reader }
+        }
+
+        fn send(&mut self, request: &str) {
+            writeln!(self.stream, "{}", request).unwrap();
+        }
+
+        fn receive(&mut self) -> Value {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            serde_json::from_str(&line).unwrap()
+        }
+
+        fn request(&mut self, request: &str) -> Value {
+            self.send(request);
+            self.receive()
+        }
+    }
+
+    #[test]
+    fn test_eval_in_separate_sessions() {
+        let mut client = Client::connect(start_server());
+
+        let s1 = client.request(r#"{"op": "clone"}"#)["new-session"].clone();
+        let s2 = client.request(r#"{"op": "clone"}"#)["new-session"].clone();
+        assert_ne!(s1, s2);
+
+        let reply = client.request(&format!(
+            r#"{{"op": "eval", "id": 7, "session": {}, "code": "(def x 1) (println \"hi\") (+ x 1)"}}"#,
+            s1
+        ));
+        assert_eq!(reply["id"], 7);
+        assert_eq!(reply["status"], "done");
+        assert_eq!(reply["values"], json!(["nil", "nil", "2"]));
+        assert_eq!(reply["out"], "hi\n");
+
+        let reply = client.request(&format!(
+            r#"{{"op": "eval", "session": {}, "code": "x"}}"#,
+            s2
+        ));
+        assert_eq!(reply["status"], "error");
+        assert_eq!(
+            reply["error"],
+            "Runtime error (REPL:1:1): Undefined name: x\n  |\n1 | x\n  | ^"
+        );
+
+        let reply = client.request(&format!(
+            r#"{{"op": "complete", "session": {}, "line": "(prin"}}"#,
+            s1
+        ));
+        assert_eq!(reply["start"], 1);
+        assert_eq!(reply["completions"], json!(["print", "println"]));
+
+        let reply = client.request(&format!(r#"{{"op": "close", "session": {}}}"#, s1));
+        assert_eq!(reply["status"], "done");
+        let reply = client.request(&format!(
+            r#"{{"op": "eval", "session": {}, "code": "x"}}"#,
+            s1
+        ));
+        assert_eq!(reply["error"], "Unknown session: 1");
+    }
+
+    #[test]
+    fn test_session_cannot_exit_server() {
+        let address = start_server();
+        let mut client = Client::connect(address);
+        let session = client.request(r#"{"op": "clone"}"#)["new-session"].clone();
+
+        let reply = client.request(&format!(
+            r#"{{"op": "eval", "session": {}, "code": "(exit 3)"}}"#,
+            session
+        ));
+        assert_eq!(
+            reply["error"],
+            "Runtime error (REPL:1:2): Undefined name: exit\n  |\n1 | (exit 3)\n  |  ^^^^"
+        );
+
+        let mut other = Client::connect(address);
+        let reply = other.request(r#"{"op": "eval", "code": "(+ 1 2)"}"#);
+        assert_eq!(reply["values"], json!(["3"]));
+    }
+
+    #[test]
+    fn test_sessions_cannot_reach_host_unless_trusted() {
+        let mut client = Client::connect(start_server());
+
+        for code in &["(run-process \"true\")", "(setenv \"X\" \"1\")", "(load \"x.mn\")"] {
+            let reply = client.request(&json!({"op": "eval", "code": code}).to_string());
+            assert!(reply["error"].as_str().unwrap().contains("Undefined name"));
+        }
+        let reply = client.request(r#"{"op": "load-file", "path": "examples/prelude.mn"}"#);
+        assert_eq!(
+            reply["error"],
+            "Loading files requires a server that trusts its clients"
+        );
+
+        let config = ServerConfig {
+            trust_clients: true,
+        };
+        let mut trusted = Client::connect(start_server_with_config(config));
+        let reply = trusted.request(r#"{"op": "eval", "code": "(file-exists? \"examples\")"}"#);
+        assert_eq!(reply["values"], json!(["true"]));
+        let reply = trusted.request(r#"{"op": "eval", "code": "(exit 3)"}"#);
+        assert_eq!(
+            reply["error"],
+            "Runtime error (REPL:1:2): Undefined name: exit\n  |\n1 | (exit 3)\n  |  ^^^^"
+        );
+    }
+
+    #[test]
+    fn test_load_file_and_bad_requests() {
+        let config = ServerConfig {
+            trust_clients: true,
+        };
+        let mut client = Client::connect(start_server_with_config(config));
+
+        let reply = client.request(r#"{"op": "load-file", "path": "examples/prelude.mn"}"#);
+        assert_eq!(reply["status"], "done");
+        assert_eq!(reply["session"], Value::Null);
+
+        let reply = client.request(r#"{"op": "eval", "code": "(>= 2 1)"}"#);
+        assert_eq!(reply["values"], json!(["true"]));
+
+        let reply = client.request("not json");
+        assert_eq!(reply["status"], "error");
+        let reply = client.request(r#"{"op": "frobnicate"}"#);
+        assert_eq!(reply["error"], "Unknown op: frobnicate");
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let address = start_server();
+        let mut client = Client::connect(address);
+        let session = client.request(r#"{"op": "clone"}"#)["new-session"].clone();
+
+        client.send(&format!(
+            r#"{{"op": "eval", "id": "loop", "session": {}, "code": "(for i 0 2000000000 i)"}}"#,
+            session
+        ));
+        thread::sleep(::std::time::Duration::from_millis(100));
+
+        let mut other = Client::connect(address);
+        let reply = other.request(&format!(r#"{{"op": "interrupt", "session": {}}}"#, session));
+        assert_eq!(reply["status"], "done");
+
+        let reply = client.receive();
+        assert_eq!(reply["id"], "loop");
+        // Where evaluation stops depends on when the interrupt arrives
+        let error = reply["error"].as_str().unwrap();
+        assert!(error.starts_with("Runtime error (REPL:1:"));
+        assert!(error.contains("): Evaluation interrupted\n"));
+    }
+}
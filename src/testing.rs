@@ -0,0 +1,272 @@
+/* The test runner behind `macaroon test`.
+
+A test file is any source file that uses deftest at its top level. Evaluating the file only
+registers its tests, along with any fixtures registered by use-fixture. Each test is then run in
+a fresh root environment, in which the file's top-level forms have been evaluated again, so
+that no test can see what another test did. */
+
+use ast::{Node, ReaderObj, Val, WriterObj};
+use back;
+use back::context::TestDef;
+use back::env::{Env, SmartEnv};
+use back::runtime_error::RuntimeError;
+use coverage::Coverage;
+use front;
+use loc::Loc;
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+
+pub struct TestResult {
+    pub name: String,
+    pub loc: Loc,
+    // The formatted error that failed the test, followed by anything the test wrote
+    pub failure: Option<String>,
+}
+
+impl TestResult {
+    pub fn display(&self) -> String {
+        let status = if self.failure.is_some() {
+            "FAIL"
+        } else {
+            "ok  "
+        };
+        match self.loc {
+            Loc::File {
+                ref filename,
+                line,
+                column,
+                ..
+            } => format!(
+                "{} {} ({}:{}:{})",
+                status, self.name, filename, line, column
+            ),
+            Loc::Unknown => format!("{} {}", status, self.name),
+        }
+    }
+}
+
+fn reader_function() -> Result<String, String> {
+    Err("Input cannot be read while testing".to_string())
+}
+
+// Whether the source uses deftest at its top level
+pub fn defines_tests(input: &str) -> bool {
+    let nodes = match front::parse("", input) {
+        Ok(nodes) => nodes,
+        Err(_) => return false,
+    };
+    nodes.iter().any(|node| match node.val {
+        Val::List(ref children) if !children.is_empty() => {
+            children[0].val == Val::Symbol("deftest".to_string())
+        }
+        _ => false,
+    })
+}
+
+// Runs each test the file defines, in order. Fails if the file can't be read, parsed or
+// evaluated, returning the formatted error.
+pub fn run_file(path: &str) -> Result<Vec<TestResult>, String> {
+    run_tests(path, None)
+}
+
+// Like run_file, adding the coverage of each test, and of evaluating the file for it, to the
+// given coverage
+pub fn run_file_with_coverage(
+    path: &str,
+    coverage: &mut Coverage,
+) -> Result<Vec<TestResult>, String> {
+    run_tests(path, Some(coverage))
+}
+
+fn run_tests(path: &str, mut coverage: Option<&mut Coverage>) -> Result<Vec<TestResult>, String> {
+    let input = match fs::read_to_string(path) {
+        Ok(input) => input,
+        Err(e) => return Err(format!("Unable to read file '{}': {}\n", path, e)),
+    };
+    let nodes = match front::parse(path, &input) {
+        Ok(nodes) => nodes,
+        Err(errors) => return Err(::format_syntax_errors_in_source(&errors, &input)),
+    };
+
+    let env = prepare(path, &input, &nodes, WriterObj::Sink, false)?;
+    let count = env.borrow().context.tests().len();
+
+    let mut results = Vec::new();
+    for i in 0..count {
+        let buffer = Rc::new(RefCell::new(Vec::<u8>::new()));
+        let writer = WriterObj::Buffer(Rc::clone(&buffer));
+        let outcome = prepare(path, &input, &nodes, writer, coverage.is_some()).map(|env| {
+            let test = env.borrow().context.tests()[i].clone();
+            let result = run_test(&env, &test)
+                .map_err(|e| ::format_runtime_error_in_source(&e, path, &input));
+            if let (Some(ref mut coverage), Some(hits)) = (&mut coverage, back::take_coverage(&env))
+            {
+                coverage.merge(&hits);
+            }
+            (test, result)
+        });
+
+        let output = String::from_utf8_lossy(&buffer.borrow()).into_owned();
+        let with_output = |error: String| {
+            if output.is_empty() {
+                error
+            } else {
+                format!("{}Output:\n{}", error, output)
+            }
+        };
+
+        // Were the file to fail only when evaluated again, the test is reported as failing
+        let discovered = &env.borrow().context.tests()[i];
+        results.push(match outcome {
+            Ok((test, result)) => TestResult {
+                name: test.name,
+                loc: test.loc,
+                failure: result.err().map(with_output),
+            },
+            Err(error) => TestResult {
+                name: discovered.name.clone(),
+                loc: discovered.loc.clone(),
+                failure: Some(with_output(error)),
+            },
+        });
+    }
+    Ok(results)
+}
+
+// Creates a root environment in which the top-level forms have been evaluated
+fn prepare(
+    path: &str,
+    input: &str,
+    nodes: &[Node],
+    writer: WriterObj,
+    with_coverage: bool,
+) -> Result<SmartEnv, String> {
+    let env = back::create_root_env(writer, ReaderObj { reader_function })
+        .map_err(|e| ::format_runtime_error(&e))?;
+    if with_coverage {
+        back::enable_coverage(&env);
+    }
+    match back::eval(Rc::clone(&env), nodes.to_vec()) {
+        Ok(_) => Ok(env),
+        Err(e) => Err(::format_runtime_error_in_source(&e, path, input)),
+    }
+}
+
+// Calls the test's routine, wrapped in each fixture, the first registered being the outermost
+fn run_test(env: &SmartEnv, test: &TestDef) -> Result<Node, RuntimeError> {
+    let mut thunk = test.routine.clone();
+    for fixture in env.borrow().context.fixtures().into_iter().rev() {
+        let wrapper_env = Env::new(Some(Rc::clone(env)));
+        wrapper_env.borrow_mut().define("fixture", fixture)?;
+        wrapper_env.borrow_mut().define("test", thunk)?;
+
+        let wrapper = list(vec![
+            symbol("fn"),
+            list(Vec::new()),
+            list(vec![symbol("fixture"), symbol("test")]),
+        ]);
+        thunk = back::eval(wrapper_env, vec![wrapper])?;
+    }
+
+    let call_env = Env::new(Some(Rc::clone(env)));
+    call_env.borrow_mut().define("test", thunk)?;
+    back::eval(call_env, vec![list(vec![symbol("test")])])
+}
+
+fn symbol(name: &str) -> Node {
+    Node::new(Val::Symbol(name.to_string()), Loc::Unknown)
+}
+
+fn list(children: Vec<Node>) -> Node {
+    Node::new(Val::List(children), Loc::Unknown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn run_source(name: &str, input: &str) -> Result<Vec<(String, Option<String>)>, String> {
+        let path = env::temp_dir().join(name);
+        fs::write(&path, input).unwrap();
+        let results = run_file(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+
+        let path = path.to_str().unwrap().to_string();
+        results
+            .map(|results| {
+                results
+                    .into_iter()
+                    .map(|r| (r.name, r.failure.map(|f| f.replace(&path, "t.mn"))))
+                    .collect()
+            })
+            .map_err(|e| e.replace(&path, "t.mn"))
+    }
+
+    #[test]
+    fn test_defines_tests() {
+        assert!(defines_tests("(def x 1)\n(deftest \"x\" (is x))"));
+        assert!(!defines_tests("(def x 1)\n(list (deftest \"x\" (is x)))"));
+        assert!(!defines_tests("(deftest"));
+    }
+
+    #[test]
+    fn test_results_and_isolation() {
+        let results = run_source(
+            "macaroon-testing-results.mn",
+            "(def count (cell 0))\n\
+             (deftest \"first\" (set-cell! count 1) (assert= 1 (get-cell count)))\n\
+             (deftest second (print \"hi\") (assert= 0 (get-cell count)) (is false))\n\
+             (deftest \"third\" (is (= 1 2) \"not equal\"))",
+        );
+
+        assert_eq!(
+            results,
+            Ok(vec![
+                ("first".to_string(), None),
+                (
+                    "second".to_string(),
+                    Some(
+                        "Runtime error (t.mn:3:59): Assertion failed: Expected a true value, but got: false\n  |\n3 | (deftest second (print \"hi\") (assert= 0 (get-cell count)) (is false))\n  |                                                           ^^^^^^^^^^\nOutput:\nhi"
+                            .to_string()
+                    )
+                ),
+                (
+                    "third".to_string(),
+                    Some(
+                        "Runtime error (t.mn:4:18): Assertion failed: not equal\n  |\n4 | (deftest \"third\" (is (= 1 2) \"not equal\"))\n  |                  ^^^^^^^^^^^^^^^^^^^^^^^^\n"
+                            .to_string()
+                    )
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_fixtures_wrap_each_test() {
+        let results = run_source(
+            "macaroon-testing-fixtures.mn",
+            "(def log (cell '()))\n\
+             (use-fixture (fn (t) (begin (set-cell! log (cons 'outer (get-cell log))) (t))))\n\
+             (use-fixture (fn (t) (begin (set-cell! log (cons 'inner (get-cell log))) (t))))\n\
+             (deftest \"order\" (assert= '(inner outer) (get-cell log)))",
+        );
+        assert_eq!(results, Ok(vec![("order".to_string(), None)]));
+    }
+
+    #[test]
+    fn test_file_errors() {
+        let results = run_source(
+            "macaroon-testing-errors.mn",
+            "(deftest \"x\" (is true))\n(car)",
+        );
+        assert_eq!(
+            results,
+            Err(
+                "Runtime error (t.mn:2:2): Undefined name: car\n  |\n2 | (car)\n  |  ^^^\n"
+                    .to_string()
+            )
+        );
+    }
+}